@@ -0,0 +1,76 @@
+use aw_watcher_window_rs::event::event_id;
+use aw_watcher_window_rs::rules::{compile_patterns, title_similarity, TitleRules};
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde_json::{Map, Value};
+
+fn patterns(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("App{}\\.exe", i)).collect()
+}
+
+fn window_data() -> Map<String, Value> {
+    let mut data = Map::new();
+    data.insert("app".to_string(), Value::String("firefox.exe".to_string()));
+    data.insert(
+        "title".to_string(),
+        Value::String("aw-watcher-window-rs - Mozilla Firefox".to_string()),
+    );
+    data
+}
+
+fn title_resolution(c: &mut Criterion) {
+    let rules = TitleRules::new(
+        true,
+        &[],
+        &["firefox\\.exe".to_string(), "Code\\.exe".to_string()],
+        false,
+    );
+    c.bench_function("title resolution", |b| {
+        b.iter(|| rules.redacts(black_box("firefox.exe")))
+    });
+}
+
+fn pattern_matching(c: &mut Criterion) {
+    let rules = TitleRules::new(false, &patterns(50), &[], false);
+    c.bench_function("50 patterns, no match", |b| {
+        b.iter(|| rules.redacts(black_box("firefox.exe")))
+    });
+    c.bench_function("50 patterns, last matches", |b| {
+        b.iter(|| rules.redacts(black_box("App49.exe")))
+    });
+    c.bench_function("compile 50 patterns", |b| {
+        let patterns = patterns(50);
+        b.iter(|| compile_patterns(black_box(&patterns), false))
+    });
+}
+
+fn similarity(c: &mut Criterion) {
+    c.bench_function("title similarity", |b| {
+        b.iter(|| {
+            title_similarity(
+                black_box("Downloading 42% - aw-watcher-window-rs - Mozilla Firefox"),
+                black_box("Downloading 43% - aw-watcher-window-rs - Mozilla Firefox"),
+            )
+        })
+    });
+}
+
+fn event_serialization(c: &mut Criterion) {
+    let data = window_data();
+    let timestamp = Utc::now();
+    c.bench_function("event data serialization", |b| {
+        b.iter(|| serde_json::to_string(black_box(&data)).unwrap())
+    });
+    c.bench_function("event id", |b| {
+        b.iter(|| event_id(black_box("aw-watcher-window-rs_host"), timestamp, &data))
+    });
+}
+
+criterion_group!(
+    benches,
+    title_resolution,
+    pattern_matching,
+    similarity,
+    event_serialization
+);
+criterion_main!(benches);
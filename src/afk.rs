@@ -0,0 +1,137 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// One heartbeat for the AFK bucket.
+pub struct Heartbeat {
+    pub afk: bool,
+    pub timestamp: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+/// Follows whether the user is away from the keyboard the way aw-watcher-afk
+/// does, so the heartbeats look the same to aw-server and its clients. A
+/// status change is dated back to the last input, which is when it actually
+/// happened.
+pub struct Afk {
+    timeout: Duration,
+    afk: bool,
+}
+
+impl Afk {
+    /// Counts the user as AFK once there was no input for `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Afk {
+            timeout,
+            afk: false,
+        }
+    }
+
+    /// Returns the heartbeats for a poll at `now`, when the last input was
+    /// `idle` ago. On a status change, the old status is extended up to the
+    /// last input and the new one starts 1ms after it.
+    pub fn poll(&mut self, now: DateTime<Utc>, idle: Duration) -> Vec<Heartbeat> {
+        let last_input = now - idle;
+        let afk = idle >= self.timeout;
+        let current = |afk: bool, timestamp: DateTime<Utc>| Heartbeat {
+            afk,
+            timestamp,
+            // An AFK event lasts until now, while a not-AFK one only lasts
+            // until the last input.
+            duration: if afk {
+                now - timestamp
+            } else {
+                Duration::zero()
+            },
+        };
+        if afk == self.afk {
+            return vec![current(afk, last_input)];
+        }
+        let closing = Heartbeat {
+            afk: self.afk,
+            timestamp: last_input,
+            duration: Duration::zero(),
+        };
+        self.afk = afk;
+        vec![
+            closing,
+            current(afk, last_input + Duration::milliseconds(1)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn summary(heartbeats: &[Heartbeat]) -> Vec<(bool, DateTime<Utc>, Duration)> {
+        heartbeats
+            .iter()
+            .map(|h| (h.afk, h.timestamp, h.duration))
+            .collect()
+    }
+
+    #[test]
+    fn recent_input_is_not_afk() {
+        let mut afk = Afk::new(Duration::seconds(180));
+        let heartbeats = afk.poll(time(100), Duration::seconds(10));
+        assert_eq!(summary(&heartbeats), [(false, time(90), Duration::zero())]);
+    }
+
+    #[test]
+    fn becomes_afk_at_the_timeout_dated_back_to_the_last_input() {
+        let mut afk = Afk::new(Duration::seconds(180));
+        afk.poll(time(100), Duration::seconds(10));
+        let heartbeats = afk.poll(time(300), Duration::seconds(180));
+        let afk_start = time(120) + Duration::milliseconds(1);
+        assert_eq!(
+            summary(&heartbeats),
+            [
+                (false, time(120), Duration::zero()),
+                (true, afk_start, time(300) - afk_start),
+            ]
+        );
+    }
+
+    #[test]
+    fn stays_afk_until_input() {
+        let mut afk = Afk::new(Duration::seconds(180));
+        afk.poll(time(300), Duration::seconds(200));
+        let heartbeats = afk.poll(time(400), Duration::seconds(300));
+        assert_eq!(
+            summary(&heartbeats),
+            [(true, time(100), Duration::seconds(300))]
+        );
+    }
+
+    #[test]
+    fn input_ends_afk_at_the_input() {
+        let mut afk = Afk::new(Duration::seconds(180));
+        afk.poll(time(300), Duration::seconds(200));
+        let heartbeats = afk.poll(time(400), Duration::seconds(5));
+        assert_eq!(
+            summary(&heartbeats),
+            [
+                (true, time(395), Duration::zero()),
+                (
+                    false,
+                    time(395) + Duration::milliseconds(1),
+                    Duration::zero()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn idle_just_below_the_timeout_is_not_afk() {
+        let mut afk = Afk::new(Duration::seconds(180));
+        let heartbeats = afk.poll(
+            time(300),
+            Duration::seconds(180) - Duration::milliseconds(1),
+        );
+        assert_eq!(heartbeats.len(), 1);
+        assert!(!heartbeats[0].afk);
+    }
+}
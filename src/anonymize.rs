@@ -0,0 +1,11 @@
+use sha2::{Digest, Sha256};
+
+/// Replaces `value` with a salted SHA-256 hash. The same value and salt
+/// always give the same hash, but the original cannot be recovered from it.
+pub fn hash(salt: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
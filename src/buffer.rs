@@ -0,0 +1,148 @@
+use crate::log::{info, warn};
+use clap::ValueEnum;
+use std::collections::VecDeque;
+
+/// What to do with a heartbeat that fails while the retry buffer is full.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Overflow {
+    /// Drop the oldest queued heartbeat to make room
+    DropOldest,
+    /// Drop the heartbeat that just failed
+    DropNewest,
+    /// Stop polling until the server accepts heartbeats again
+    Block,
+}
+
+/// Heartbeats waiting to be resent, in the order they were produced, up to
+/// an optional maximum.
+pub struct Buffer<T> {
+    items: VecDeque<T>,
+    max: Option<usize>,
+    overflow: Overflow,
+    /// Heartbeats dropped since the buffer last ran empty.
+    dropped: u64,
+}
+
+impl<T> Buffer<T> {
+    pub fn new(max: Option<usize>, overflow: Overflow) -> Self {
+        Buffer {
+            items: VecDeque::new(),
+            max,
+            overflow,
+            dropped: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max.is_some_and(|max| self.items.len() >= max)
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    /// Removes the oldest heartbeat once it has been resent.
+    pub fn pop_front(&mut self) {
+        self.items.pop_front();
+        if self.items.is_empty() && self.dropped > 0 {
+            info!(
+                "Retry buffer drained. {} heartbeat(s) were dropped while it was full",
+                self.dropped
+            );
+            self.dropped = 0;
+        }
+    }
+
+    /// Queues `item`, dropping a heartbeat as the overflow policy says if
+    /// the buffer is full. With `Overflow::Block`, callers wait for room
+    /// before pushing.
+    pub fn push(&mut self, item: T) {
+        if self.is_full() {
+            if self.dropped == 0 {
+                warn!(
+                    "Retry buffer is full with {} heartbeat(s). Dropping heartbeats until the server accepts them again",
+                    self.items.len()
+                );
+            }
+            self.dropped += 1;
+            match self.overflow {
+                Overflow::DropOldest => {
+                    self.items.pop_front();
+                }
+                Overflow::DropNewest | Overflow::Block => return,
+            }
+        }
+        self.items.push_back(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(buffer: &mut Buffer<u32>) -> Vec<u32> {
+        let mut items = Vec::new();
+        while let Some(&item) = buffer.front() {
+            items.push(item);
+            buffer.pop_front();
+        }
+        items
+    }
+
+    #[test]
+    fn resends_in_the_order_produced() {
+        let mut buffer = Buffer::new(None, Overflow::DropOldest);
+        for item in 1..=5 {
+            buffer.push(item);
+        }
+        assert!(!buffer.is_full());
+        assert_eq!(drain(&mut buffer), [1, 2, 3, 4, 5]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn drop_oldest_evicts_the_front() {
+        let mut buffer = Buffer::new(Some(3), Overflow::DropOldest);
+        for item in 1..=5 {
+            buffer.push(item);
+        }
+        assert!(buffer.is_full());
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(drain(&mut buffer), [3, 4, 5]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_what_is_queued() {
+        for overflow in [Overflow::DropNewest, Overflow::Block] {
+            let mut buffer = Buffer::new(Some(3), overflow);
+            for item in 1..=5 {
+                buffer.push(item);
+            }
+            assert_eq!(drain(&mut buffer), [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn has_room_again_once_resent() {
+        let mut buffer = Buffer::new(Some(2), Overflow::DropNewest);
+        buffer.push(1);
+        buffer.push(2);
+        buffer.pop_front();
+        assert!(!buffer.is_full());
+        buffer.push(3);
+        assert_eq!(buffer.dropped, 0);
+        buffer.push(4);
+        assert_eq!(buffer.dropped, 1);
+        assert_eq!(drain(&mut buffer), [2, 3]);
+        // The count starts over once the buffer ran empty.
+        assert_eq!(buffer.dropped, 0);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::log::warn;
+#[cfg(feature = "uia")]
+use crate::uia::Uia;
+use windows::Win32::UI::WindowsAndMessaging::GetShellWindow;
+use winsafe::{co, prelude::*, SysResult, HPROCESS, HWND};
+
+/// Which queries the watcher's token allows. Under a restricted or
+/// low-integrity token, some of them are denied for every window, and
+/// trying them anyway would only log the same error on every poll.
+pub struct Capabilities {
+    /// Whether other processes can be opened to read their executable path.
+    pub process_query: bool,
+    /// Whether UI Automation can read other processes' windows.
+    #[cfg_attr(not(feature = "uia"), allow(dead_code))]
+    pub uia: bool,
+}
+
+/// Tries each query once against the shell's window, which belongs to
+/// another process at normal integrity, and disables the denied ones.
+/// `uia_options` are the options given that need UI Automation.
+pub fn probe(uia_options: &[&str]) -> Capabilities {
+    let uia = !uia_options.is_empty();
+    let shell = unsafe { GetShellWindow() };
+    if shell.is_invalid() {
+        // Nothing to probe against, e.g. in a session without a shell.
+        return Capabilities {
+            process_query: true,
+            uia,
+        };
+    }
+    let shell = unsafe { HWND::from_ptr(shell.0) };
+
+    let process_query = match query_process(&shell) {
+        Err(co::ERROR::ACCESS_DENIED) => {
+            warn!("Other processes cannot be queried under this token. Apps are reported by window class instead");
+            false
+        }
+        _ => true,
+    };
+
+    #[cfg(feature = "uia")]
+    let uia = uia && {
+        match Uia::new().and_then(|uia| uia.window_name(&shell)) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!(
+                    "UI Automation is not available under this token: {}. {} disabled",
+                    e,
+                    listed(uia_options)
+                );
+                false
+            }
+        }
+    };
+
+    Capabilities { process_query, uia }
+}
+
+/// Joins `options` as in "a, b and c are", or "a is" for just one.
+#[cfg(feature = "uia")]
+fn listed(options: &[&str]) -> String {
+    match options {
+        [] => String::new(),
+        [option] => format!("{} is", option),
+        [rest @ .., last] => format!("{} and {} are", rest.join(", "), last),
+    }
+}
+
+fn query_process(hwnd: &HWND) -> SysResult<()> {
+    let (_, process_id) = hwnd.GetWindowThreadProcessId();
+    let process = HPROCESS::OpenProcess(co::PROCESS::QUERY_INFORMATION, false, process_id)?;
+    process.QueryFullProcessImageName(co::PROCESS_NAME::WIN32)?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use windows::core::Result;
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+
+/// Initializes COM on the calling thread. Calling it again on the same
+/// thread is harmless.
+pub fn initialize() -> Result<()> {
+    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).ok() }
+}
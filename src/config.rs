@@ -0,0 +1,119 @@
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use toml::{Table, Value};
+
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Where the config file is read from unless --config is given, next to
+/// the other ActivityWatch components' configuration.
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| {
+        PathBuf::from(dir)
+            .join("activitywatch")
+            .join("aw-watcher-window-rs")
+            .join("config.toml")
+    })
+}
+
+/// Turns the config file at `path` into options for `command`, in the form
+/// they would be given on the command line. Options that `matches` already
+/// has from the command line are left out, so the command line wins.
+///
+/// Keys are option names or their aliases, with either dashes or
+/// underscores, such as `poll_time = "1s"`. Arrays give repeated options,
+/// and `true` turns a flag on.
+pub fn read_args(
+    path: &Path,
+    command: &Command,
+    matches: &ArgMatches,
+) -> Result<Vec<OsString>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let table = text
+        .parse::<Table>()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| {
+                let aliases = arg.get_all_aliases().unwrap_or_default();
+                (arg.get_long() == Some(name.as_str()) || aliases.contains(&name.as_str()))
+                    && name != "config"
+            })
+            .ok_or_else(|| format!("{} sets '{}', which is not an option", path.display(), key))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = matches!(arg.get_action(), ArgAction::SetTrue);
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::Boolean(on) if flag => {
+                    if on {
+                        args.push(format!("--{}", name).into());
+                    }
+                    continue;
+                }
+                Value::String(value) => value,
+                Value::Integer(value) => value.to_string(),
+                Value::Float(value) => value.to_string(),
+                Value::Boolean(value) => value.to_string(),
+                _ => {
+                    return Err(format!(
+                        "{} sets '{}' to a table or date, which no option takes",
+                        path.display(),
+                        key
+                    ))
+                }
+            };
+            args.push(format!("--{}={}", name, value).into());
+        }
+    }
+    Ok(args)
+}
+
+/// Starts watching the config file at `path` for changes, which are
+/// reported by `changed` until taken with `take_change`. Watching stops
+/// when the returned watcher is dropped.
+pub fn watch(path: &Path) -> notify::Result<RecommendedWatcher> {
+    let name = path.file_name().map(|name| name.to_os_string());
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        // Editors often save by writing a new file and renaming it over the
+        // old one, so the directory is watched instead of the file.
+        let ours = event
+            .paths
+            .iter()
+            .any(|path| path.file_name().map(|name| name.to_os_string()) == name);
+        if ours && (event.kind.is_create() || event.kind.is_modify()) {
+            CHANGED.store(true, Ordering::SeqCst);
+        }
+    })?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// Returns whether the config file changed since the last `take_change`.
+pub fn changed() -> bool {
+    CHANGED.load(Ordering::SeqCst)
+}
+
+/// Returns whether the config file changed, and clears the change.
+pub fn take_change() -> bool {
+    CHANGED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,10 @@
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+/// Attaches to the console of the terminal that started the watcher, if
+/// any. Release builds are GUI programs without a console of their own, so
+/// otherwise their output and Ctrl+C would go nowhere. Started at login or
+/// by aw-qt, there is no such console and nothing changes.
+pub fn attach_parent() {
+    // Fails harmlessly in debug builds, which have a console already.
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
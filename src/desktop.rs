@@ -0,0 +1,36 @@
+use crate::com;
+use windows::core::Result;
+use windows::Win32::Foundation::HWND as RawHwnd;
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
+use windows::Win32::UI::Shell::{IVirtualDesktopManager, VirtualDesktopManager};
+use winsafe::{prelude::*, HWND};
+
+/// Virtual desktop queries through IVirtualDesktopManager.
+pub struct VirtualDesktops {
+    manager: IVirtualDesktopManager,
+}
+
+impl VirtualDesktops {
+    pub fn new() -> Result<Self> {
+        com::initialize()?;
+        let manager = unsafe { CoCreateInstance(&VirtualDesktopManager, None, CLSCTX_ALL)? };
+        Ok(VirtualDesktops { manager })
+    }
+
+    pub fn is_on_current(&self, hwnd: &HWND) -> Result<bool> {
+        unsafe {
+            self.manager
+                .IsWindowOnCurrentVirtualDesktop(RawHwnd(hwnd.ptr()))
+                .map(|on_current| on_current.as_bool())
+        }
+    }
+
+    /// Returns the GUID of the desktop `hwnd` is on.
+    pub fn desktop_id(&self, hwnd: &HWND) -> Result<String> {
+        unsafe {
+            self.manager
+                .GetWindowDesktopId(RawHwnd(hwnd.ptr()))
+                .map(|id| format!("{:?}", id))
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// Parses a duration into milliseconds. A bare number is taken as
+/// milliseconds for compatibility; otherwise it needs one of the units
+/// `ms`, `s`, `m` or `h`, as in `500ms`, `5s` or `1.5m`.
+pub fn parse_millis(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("'{}' is not a duration", s))?;
+    let scale = match unit.trim() {
+        "" | "ms" => 1.0,
+        "s" => 1_000.0,
+        "m" => 60_000.0,
+        "h" => 3_600_000.0,
+        other => {
+            return Err(format!(
+                "unknown unit '{}' in '{}', expected ms, s, m or h",
+                other, s
+            ))
+        }
+    };
+    let millis = (number * scale).round();
+    if millis > u32::MAX as f64 {
+        return Err(format!("'{}' is too long", s));
+    }
+    Ok(millis as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_numbers_are_milliseconds() {
+        assert_eq!(parse_millis("500"), Ok(500));
+        assert_eq!(parse_millis(" 0 "), Ok(0));
+    }
+
+    #[test]
+    fn units_scale_the_number() {
+        assert_eq!(parse_millis("250ms"), Ok(250));
+        assert_eq!(parse_millis("5s"), Ok(5_000));
+        assert_eq!(parse_millis("2m"), Ok(120_000));
+        assert_eq!(parse_millis("1h"), Ok(3_600_000));
+        assert_eq!(parse_millis("5 s"), Ok(5_000));
+    }
+
+    #[test]
+    fn decimals_are_rounded_to_milliseconds() {
+        assert_eq!(parse_millis("1.5m"), Ok(90_000));
+        assert_eq!(parse_millis("0.25s"), Ok(250));
+        assert_eq!(parse_millis("1.6"), Ok(2));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_duration() {
+        assert!(parse_millis("").is_err());
+        assert!(parse_millis("s").is_err());
+        assert!(parse_millis("-5s").is_err());
+        assert!(parse_millis("1.2.3s").is_err());
+        assert_eq!(
+            parse_millis("5d"),
+            Err("unknown unit 'd' in '5d', expected ms, s, m or h".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_durations_past_u32() {
+        assert_eq!(parse_millis("4294967295"), Ok(u32::MAX));
+        assert_eq!(
+            parse_millis("4294967296"),
+            Err("'4294967296' is too long".to_string())
+        );
+        assert_eq!(parse_millis("1193h"), Ok(4_294_800_000));
+        assert!(parse_millis("1194h").is_err());
+    }
+}
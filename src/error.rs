@@ -0,0 +1,57 @@
+use thiserror::Error;
+
+/// Why a request to aw-server, or reading the local database, failed.
+#[derive(Debug, Error)]
+pub enum WatcherError {
+    /// The server could not be reached or did not answer in time.
+    #[error("could not reach aw-server: {0}")]
+    Network(#[source] reqwest::Error),
+    /// The server answered with an error status.
+    #[error("aw-server rejected the request with {status}")]
+    Rejected {
+        status: reqwest::StatusCode,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The request or the server's response could not be (de)serialized.
+    #[error("could not encode or decode aw-server data: {0}")]
+    Serialization(#[source] reqwest::Error),
+    /// The server did not answer within --request-timeout milliseconds.
+    #[error("aw-server did not answer within {0}ms")]
+    TimedOut(u32),
+    /// A request that timed out earlier has not finished yet, so this one
+    /// was not made.
+    #[error("an earlier request to aw-server that timed out has not finished yet")]
+    Busy,
+    /// The local SQLite database could not be read.
+    #[cfg(feature = "sqlite")]
+    #[error("could not read the SQLite database: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+impl From<reqwest::Error> for WatcherError {
+    fn from(e: reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            WatcherError::Rejected { status, source: e }
+        } else if e.is_decode() || e.is_body() || e.is_builder() {
+            WatcherError::Serialization(e)
+        } else {
+            WatcherError::Network(e)
+        }
+    }
+}
+
+impl WatcherError {
+    /// Returns whether the server refused to create a bucket because it
+    /// already exists. aw-server answers 304 Not Modified, and other
+    /// implementations answer 409 Conflict.
+    pub fn is_already_exists(&self) -> bool {
+        match self {
+            WatcherError::Rejected { status, .. } => {
+                *status == reqwest::StatusCode::NOT_MODIFIED
+                    || *status == reqwest::StatusCode::CONFLICT
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+/// Derives an event id from the bucket, timestamp, app and title.
+///
+/// This is a 63-bit FNV-1a hash, so it is stable across runs and builds but
+/// not collision-free: among 100 million events the chance of any two ids
+/// colliding is about 0.05%. Events that share all four inputs get the same
+/// id by design. It is only meant for pipelines that join window events with
+/// other data outside ActivityWatch.
+pub fn event_id(bucket: &str, timestamp: DateTime<Utc>, data: &Map<String, Value>) -> i64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let timestamp = timestamp
+        .timestamp_nanos_opt()
+        .unwrap_or_default()
+        .to_string();
+    let fields = [
+        bucket,
+        timestamp.as_str(),
+        data.get("app").and_then(Value::as_str).unwrap_or_default(),
+        data.get("title")
+            .and_then(Value::as_str)
+            .unwrap_or_default(),
+    ];
+    for field in fields {
+        for byte in field.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    (hash >> 1) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(app: &str, title: &str) -> Map<String, Value> {
+        let mut data = Map::new();
+        data.insert("app".to_string(), Value::String(app.to_string()));
+        data.insert("title".to_string(), Value::String(title.to_string()));
+        data
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let id = event_id("bucket", at(1_700_000_000), &data("Code.exe", "main.rs"));
+        assert_eq!(
+            id,
+            event_id("bucket", at(1_700_000_000), &data("Code.exe", "main.rs"))
+        );
+        // Pinned so that a change to the hash, which would break joins with
+        // ids stored earlier, does not go unnoticed.
+        assert_eq!(id, 0x1838_b2a8_6ca4_a38e);
+    }
+
+    #[test]
+    fn depends_on_every_input() {
+        let id = event_id("bucket", at(1_700_000_000), &data("Code.exe", "main.rs"));
+        for other in [
+            event_id("other", at(1_700_000_000), &data("Code.exe", "main.rs")),
+            event_id("bucket", at(1_700_000_001), &data("Code.exe", "main.rs")),
+            event_id("bucket", at(1_700_000_000), &data("code.exe", "main.rs")),
+            event_id("bucket", at(1_700_000_000), &data("Code.exe", "lib.rs")),
+        ] {
+            assert_ne!(id, other);
+        }
+    }
+
+    #[test]
+    fn separates_fields() {
+        // Moving text from one field to the next must change the id.
+        assert_ne!(
+            event_id("bucket", at(0), &data("ab", "c")),
+            event_id("bucket", at(0), &data("a", "bc"))
+        );
+    }
+
+    #[test]
+    fn ignores_other_fields() {
+        let mut extra = data("Code.exe", "main.rs");
+        extra.insert("url".to_string(), Value::String("https://a".to_string()));
+        assert_eq!(
+            event_id("bucket", at(0), &extra),
+            event_id("bucket", at(0), &data("Code.exe", "main.rs"))
+        );
+    }
+
+    #[test]
+    fn is_never_negative() {
+        for secs in 0..1000 {
+            assert!(event_id("bucket", at(secs), &data("a", "b")) >= 0);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::WatcherError;
+use aw_client_rust::blocking::AwClient;
+use aw_client_rust::Event;
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// One JSON object per event with its bucket, start, duration and data
+    Jsonl,
+    /// start,end,duration,app,title rows with a header
+    Csv,
+}
+
+/// Reads the events of `buckets` that end after `since`, oldest first.
+pub fn from_server(
+    client: &AwClient,
+    buckets: &[String],
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<(String, Event)>, WatcherError> {
+    let mut events = Vec::new();
+    for bucket in buckets {
+        let bucket_events = client.get_events(bucket, since, None, None)?;
+        events.extend(
+            bucket_events
+                .into_iter()
+                .map(|event| (bucket.clone(), event)),
+        );
+    }
+    events.sort_by_key(|(_, event)| event.timestamp);
+    Ok(events)
+}
+
+/// Writes `events`, each with the bucket it came from, to `out`.
+pub fn write(events: &[(String, Event)], format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Jsonl => {
+            for (bucket, event) in events {
+                let line = serde_json::json!({
+                    "bucket": bucket,
+                    "timestamp": event.timestamp.to_rfc3339(),
+                    "duration": seconds(event),
+                    "data": event.data,
+                });
+                writeln!(out, "{}", line)?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "start,end,duration,app,title")?;
+            for (_, event) in events {
+                let field = |name: &str| {
+                    event
+                        .data
+                        .get(name)
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                };
+                writeln!(
+                    out,
+                    "{},{},{:.3},{},{}",
+                    timestamp(event.timestamp),
+                    timestamp(event.timestamp + event.duration),
+                    seconds(event),
+                    csv_field(field("app")),
+                    csv_field(field("title")),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn seconds(event: &Event) -> f64 {
+    event.duration.num_milliseconds() as f64 / 1000.0
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Quotes `value` if it contains a comma, quote or line break, doubling
+/// any quotes inside it.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,117 @@
+use crate::log::error;
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicI64, AtomicIsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use windows::Win32::Foundation::{HMODULE, HWND};
+use windows::Win32::UI::Accessibility::{SetWinEventHook, HWINEVENTHOOK, WINEVENTPROC};
+use windows::Win32::UI::WindowsAndMessaging::{
+    DispatchMessageW, GetMessageW, TranslateMessage, EVENT_OBJECT_DESTROY, EVENT_SYSTEM_FOREGROUND,
+    MSG, OBJID_WINDOW, WINEVENT_OUTOFCONTEXT,
+};
+use winsafe::prelude::*;
+
+static LAST_SWITCH: AtomicI64 = AtomicI64::new(i64::MIN);
+/// The window the poll loop is tracking, whose destruction ends its event,
+/// or CLOSED once it has been destroyed.
+static TRACKED: AtomicIsize = AtomicIsize::new(0);
+const CLOSED: isize = -1;
+/// Set on every foreground change until `wait` sees it.
+static SWITCHED: Mutex<bool> = Mutex::new(false);
+static SWITCH: Condvar = Condvar::new();
+
+/// Starts a thread that records the time of every foreground change, and of
+/// the tracked window being closed, and wakes up `wait` for both.
+pub fn spawn_hook() {
+    thread::spawn(|| unsafe {
+        let hooks: [(u32, WINEVENTPROC); 2] = [
+            (EVENT_SYSTEM_FOREGROUND, Some(on_foreground)),
+            (EVENT_OBJECT_DESTROY, Some(on_destroy)),
+        ];
+        for (event, callback) in hooks {
+            let hook = SetWinEventHook(
+                event,
+                event,
+                HMODULE::default(),
+                callback,
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            );
+            if hook.is_invalid() {
+                error!("Failed to install the foreground event hook");
+                return;
+            }
+        }
+        // Out-of-context hooks are delivered through this thread's queue.
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    });
+}
+
+/// Sets the window whose closing counts as a foreground change. When an app
+/// exits, focus only moves on once its window is gone, and the event should
+/// end then rather than whenever the next poll notices.
+pub fn track(hwnd: &winsafe::HWND) {
+    TRACKED.store(hwnd.ptr() as isize, Ordering::SeqCst);
+}
+
+/// Sleeps for `timeout`, or until the foreground changes or the tracked
+/// window closes, whichever comes first. Needs `spawn_hook` to wake early.
+pub fn wait(timeout: Duration) {
+    let switched = SWITCHED.lock().unwrap();
+    let (mut switched, _) = SWITCH
+        .wait_timeout_while(switched, timeout, |switched| !*switched)
+        .unwrap();
+    *switched = false;
+}
+
+/// Returns when the foreground last changed, if that was after `after`.
+pub fn last_switch_after(after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(LAST_SWITCH.load(Ordering::SeqCst))
+        .filter(|switch| *switch > after)
+}
+
+unsafe extern "system" fn on_foreground(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    _hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    wake();
+    // Focus moving on after the tracked window closed does not move the
+    // end of its event.
+    if TRACKED.load(Ordering::SeqCst) == CLOSED {
+        return;
+    }
+    LAST_SWITCH.store(Utc::now().timestamp_millis(), Ordering::SeqCst);
+}
+
+unsafe extern "system" fn on_destroy(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    id_object: i32,
+    _id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    if id_object != OBJID_WINDOW.0 || hwnd.0 as isize != TRACKED.load(Ordering::SeqCst) {
+        return;
+    }
+    LAST_SWITCH.store(Utc::now().timestamp_millis(), Ordering::SeqCst);
+    TRACKED.store(CLOSED, Ordering::SeqCst);
+    wake();
+}
+
+fn wake() {
+    *SWITCHED.lock().unwrap() = true;
+    SWITCH.notify_all();
+}
@@ -0,0 +1,29 @@
+use std::mem::size_of;
+use windows::Win32::Foundation::HWND as RawHwnd;
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetGUIThreadInfo, GetWindowThreadProcessId, GUITHREADINFO,
+};
+use winsafe::{prelude::*, HWND};
+
+/// Guesses whether `hwnd` is receiving input rather than only being the
+/// foreground window. It is, if its thread considers it active and has
+/// keyboard focus on it or one of its controls, or is capturing the mouse.
+/// Focus moved to another process's window, such as an embedded plugin or
+/// a floating tool window, counts as not receiving input. Returns None if
+/// the thread cannot be queried.
+pub fn receives_input(hwnd: &HWND) -> Option<bool> {
+    unsafe {
+        let hwnd = RawHwnd(hwnd.ptr());
+        let thread_id = GetWindowThreadProcessId(hwnd, None);
+        if thread_id == 0 {
+            return None;
+        }
+        let mut info = GUITHREADINFO {
+            cbSize: size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        GetGUIThreadInfo(thread_id, &mut info).ok()?;
+        let focused = info.hwndActive == hwnd && !info.hwndFocus.is_invalid();
+        Some(focused || !info.hwndCapture.is_invalid())
+    }
+}
@@ -0,0 +1,14 @@
+//! Parts of the watcher that do not talk to the OS or the server, kept in a
+//! library so they can be benchmarked on their own.
+
+pub mod afk;
+pub mod anonymize;
+pub mod duration;
+pub mod event;
+pub mod process;
+pub mod quiet;
+pub mod replay;
+pub mod rules;
+pub mod sample;
+pub mod simulate;
+pub mod template;
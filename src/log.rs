@@ -0,0 +1,98 @@
+use owo_colors::OwoColorize;
+use serde_json::{Map, Value};
+use std::fmt::{self, Arguments};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+/// Set when stdout is used for output of its own, such as --emit-current.
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// Enables debug messages if `debug` is set. With `reserve_stdout`, info
+/// and debug messages go to stderr along with warnings and errors.
+pub fn init(debug: bool, reserve_stdout: bool) {
+    DEBUG.store(debug, Ordering::Relaxed);
+    STDOUT_RESERVED.store(reserve_stdout, Ordering::Relaxed);
+}
+
+/// Prints a diagnostic message. Messages get a colored level label when the
+/// stream is a terminal and are printed unchanged when it is piped.
+pub fn write(level: Level, message: Arguments) {
+    if level == Level::Debug && !DEBUG.load(Ordering::Relaxed) {
+        return;
+    }
+    let to_stderr =
+        matches!(level, Level::Error | Level::Warn) || STDOUT_RESERVED.load(Ordering::Relaxed);
+    let terminal = if to_stderr {
+        std::io::stderr().is_terminal()
+    } else {
+        std::io::stdout().is_terminal()
+    };
+    let line = match level {
+        _ if !terminal => message.to_string(),
+        Level::Debug => format!("{} {}", label(level), message.dimmed()),
+        _ => format!("{} {}", label(level), message),
+    };
+    if to_stderr {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Formats event data as pretty-printed JSON, only when the message is
+/// actually written.
+pub struct Json<'a>(pub &'a Map<String, Value>);
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match serde_json::to_string_pretty(self.0) {
+            Ok(json) => f.write_str(&json),
+            Err(_) => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+fn label(level: Level) -> String {
+    match level {
+        Level::Error => "ERROR".red().bold().to_string(),
+        Level::Warn => " WARN".yellow().bold().to_string(),
+        Level::Info => " INFO".green().to_string(),
+        Level::Debug => "DEBUG".blue().to_string(),
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+// `warn` would be ambiguous with the built-in attribute in this `use`.
+pub(crate) use {debug, error, info, warning as warn};
@@ -1,15 +1,119 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use chrono::{Duration, Utc};
-use clap::Parser;
+// Every Windows architecture is supported, x64 and ARM64 alike, but the
+// Win32 calls have no counterpart elsewhere.
+#[cfg(not(windows))]
+compile_error!("aw-watcher-window-rs only runs on Windows");
+mod buffer;
+mod capability;
+#[cfg(any(feature = "uia", feature = "virtual-desktop"))]
+mod com;
+mod config;
+mod console;
+#[cfg(feature = "virtual-desktop")]
+mod desktop;
+mod elevation;
+mod error;
+mod export;
+mod foreground;
+mod input;
+mod log;
+mod monitors;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod package;
+mod profile;
+mod recording;
+mod report;
+mod session;
+mod shutdown;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "uia")]
+mod uia;
+mod user;
+mod version;
+mod watchdog;
+#[cfg(feature = "webhook")]
+mod webhook;
+mod window_text;
+
+use aw_watcher_window_rs::afk::Afk;
+use aw_watcher_window_rs::anonymize;
+use aw_watcher_window_rs::duration::parse_millis;
+use aw_watcher_window_rs::event::event_id;
+use aw_watcher_window_rs::process::{self, Owner, PerProcess, ProcessKey};
+use aw_watcher_window_rs::quiet::QuietHours;
+use aw_watcher_window_rs::replay::{self, Replay};
+use aw_watcher_window_rs::rules::{self, TitleRules};
+use aw_watcher_window_rs::sample::{Coalescer, Sampler};
+use aw_watcher_window_rs::simulate::Simulator;
+use aw_watcher_window_rs::template::Template;
+use buffer::{Buffer, Overflow};
+use capability::Capabilities;
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::error::ErrorKind;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "virtual-desktop")]
+use desktop::VirtualDesktops;
+use error::WatcherError;
+use log::{debug, error, info, warn};
+use profile::{timed, Profiler};
+use rand::Rng;
 use regex::Regex;
 use serde_json::{Map, Value};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
 use std::{thread, time};
+#[cfg(feature = "uia")]
+use uia::Uia;
+use watchdog::Liveness;
+use window_text::TitleSource;
 use winsafe::{co, prelude::*, HPROCESS, HWND};
 
+/// How often the poll loop reports itself alive with --liveness-bucket.
+const LIVENESS_INTERVAL: time::Duration = time::Duration::from_secs(60);
+const LIVENESS_BUCKET_TYPE: &str = "aw-watcher-window-rs.liveness";
+const DEEP_FOCUS_BUCKET_TYPE: &str = "aw-watcher-window-rs.deep-focus";
+const MONITOR_BUCKET_TYPE: &str = "aw-watcher-window-rs.monitor";
+/// The type aw-watcher-afk gives its bucket, which clients look for.
+const AFK_BUCKET_TYPE: &str = "afkstatus";
+/// How long the startup check waits for a connection to aw-server.
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+/// The longest wait between startup checks while aw-server is unreachable.
+const MAX_CONNECT_BACKOFF: time::Duration = time::Duration::from_secs(60);
+/// Heartbeats waiting to be resent. Shared between poll workers, so that a
+/// worker restarted by the watchdog takes over the queue of the one before.
+type RetryQueue = Mutex<Buffer<(String, aw_client_rust::Event)>>;
+
+/// The thread of the last request that `request` gave up on. Until it has
+/// finished, no other request is started, so that the abandoned request
+/// cannot reach the server after one that was made after it.
+static ABANDONED: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+/// The version of the event data layout sent with --include-schema-version.
+/// Bump it whenever a field is renamed or removed or changes its meaning.
+const SCHEMA_VERSION: u32 = 1;
+/// Default pulsetimes above this, from long poll times, get a warning.
+const LONG_PULSETIME_SECS: f64 = 120.0;
+/// Options --print-config never prints. Webhook URLs often carry a token.
+const SECRET_OPTIONS: &[&str] = &["hash_salt", "webhook_url"];
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Read options from this TOML file. Options given on the command line override it [default: %LOCALAPPDATA%\\activitywatch\\aw-watcher-window-rs\\config.toml, if it exists]"
+    )]
+    config: Option<PathBuf>,
+
     #[arg(
         long,
         default_value = "localhost",
@@ -17,188 +121,2993 @@ struct Args {
     )]
     host: String,
 
-    #[arg(
-        long,
-        default_value_t = 5600,
-        help = "The port of the ActivityWatch server to connect to"
-    )]
-    port: u16,
+    #[arg(
+        long,
+        help = "The port of the ActivityWatch server to connect to [default: 5600, or 5666 with --testing]"
+    )]
+    port: Option<u16>,
+
+    #[arg(long, default_value_t = false, help = "Disable title reporting")]
+    exclude_title: bool,
+
+    #[arg(short = 'e', long, alias = "exclude-title-processes", num_args = 1.., value_delimiter = ',', help = "Comma-separated list of regex patterns that matches process names (e.g., Firefox.exe) to redact titles for")]
+    redact_title_for: Vec<String>,
+
+    #[arg(short = 'i', long, alias = "include-title-processes", num_args = 1.., value_delimiter = ',', help = "Comma-separated list of regex patterns that matches process names to keep titles for. Overrides --exclude-title and --redact-title-for")]
+    keep_title_for: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report \"title_redacted\": true when the title was replaced by the process name"
+    )]
+    annotate_redaction: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Let process name patterns match anywhere in the name instead of the whole name"
+    )]
+    substring_regex: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DedupBy::App,
+        help = "Compare apps by executable name or full path when deciding whether the window changed. Only --include-path sends the path"
+    )]
+    dedup_by: DedupBy,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = AppSource::Exe,
+        help = "Report the executable name, its file description or its AppUserModelID as \"app\". Falls back to the executable name when there is none. Process patterns still match the executable name"
+    )]
+    windows_app_source: AppSource,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the full path of the app's executable as \"path\""
+    )]
+    include_path: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Ignore the parts of titles matching this regex when deciding whether the window changed. Can be repeated"
+    )]
+    title_ignore_pattern: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "RATIO",
+        value_parser = parse_fraction,
+        help = "Keep the current event when only the title changed and is still at least this similar (0.0-1.0) to the event's title, e.g. 0.9"
+    )]
+    title_similarity: Option<f64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Replace file paths and URLs in titles with <path> and <url>"
+    )]
+    scrub_paths: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX=SUFFIX",
+        value_parser = parse_route,
+        help = "Send events of apps matching the regex to a separate bucket named after the default one plus -SUFFIX. Can be repeated"
+    )]
+    route: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        value_parser = Template::parse,
+        help = "Also report a \"label\" built from this template, e.g. '{app}: {title}'. Available fields: app, title, tab, desktop, user, tz"
+    )]
+    label_template: Option<Template>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "label_template",
+        help = "Report only the label instead of app and title"
+    )]
+    label_only: bool,
+
+    #[arg(
+        long,
+        default_value_t = 5000,
+        value_parser = parse_millis,
+        help = "Poll time, e.g. 5s or 500ms. Bare numbers are milliseconds"
+    )]
+    poll_time: u32,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Only report the window that was in the foreground for most polls in each window of this length, e.g. 30s"
+    )]
+    sample_window: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        conflicts_with = "sample_window",
+        help = "Report one event per app instead of per title, with the app's most-used title in each window of this length, e.g. 30s"
+    )]
+    coalesce_app_window: Option<u32>,
+
+    #[arg(
+        long,
+        num_args = 1..,
+        value_delimiter = ',',
+        help = "Comma-separated list of the only fields that may be sent, e.g. app,title. Applies on top of all other options"
+    )]
+    fields: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Merge heartbeats with the same data that are at most this far apart [default: the poll time or sample window plus 1s]"
+    )]
+    pulsetime: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Only send a heartbeat when the window changes, plus one every --keepalive-interval, instead of on every poll"
+    )]
+    change_only_heartbeats: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        default_value = "60s",
+        help = "With --change-only-heartbeats, how often to still send a heartbeat for an unchanged window"
+    )]
+    keepalive_interval: u32,
+
+    #[arg(
+        long,
+        alias = "verbose",
+        default_value_t = false,
+        help = "Enable debug logging"
+    )]
+    debug: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Log which fields changed, with their old and new values, whenever an event ends"
+    )]
+    trace_splits: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Run for the given number of seconds, then print a timing report and exit"
+    )]
+    profile: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print every visible top-level window with its process, class and title, then exit"
+    )]
+    list_windows: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print the value of every option, including defaults, as JSON, then exit. Secrets are redacted"
+    )]
+    print_config: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Populate event ids with a deterministic 63-bit hash of bucket, timestamp, app and title. Ids are not unique: events sharing all four get the same id, and different ones may rarely collide. aw-server replaces any event already stored under an id it is sent"
+    )]
+    emit_event_id: bool,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Wait this long after startup before doing anything, e.g. 30s, to let a new session settle"
+    )]
+    startup_delay: u32,
+
+    #[arg(
+        long,
+        help = "Send a heartbeat to this bucket every minute while polling, to monitor that the watcher is alive"
+    )]
+    liveness_bucket: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Also report AFK status to aw-watcher-afk_<hostname> like aw-watcher-afk, counting as AFK after no input for this long, e.g. 3m"
+    )]
+    afk_timeout: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        conflicts_with = "completed_events",
+        help = "Give up on requests from the poll loop to aw-server after this long, e.g. 10s. Heartbeats that were given up on are not resent [default: half of --watchdog-timeout, if given]"
+    )]
+    request_timeout: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        default_value = "5s",
+        help = "On Ctrl+C, wait at most this long after the next poll for the current event to be closed and queued heartbeats to be sent"
+    )]
+    shutdown_timeout: u32,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Poll on a worker thread and restart it if it makes no progress for this long"
+    )]
+    watchdog_timeout: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Use this bucket id instead of one derived from the hostname"
+    )]
+    bucket_name: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "get-window-text,wm-gettext",
+        help = "Where to read window titles from, tried in order until one is not empty. uia and msaa need the uia feature"
+    )]
+    title_sources: Vec<TitleSource>,
+
+    #[cfg(feature = "uia")]
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the selected Windows Terminal tab as \"tab\""
+    )]
+    terminal_tabs: bool,
+
+    #[arg(
+        long,
+        value_name = "HH:MM-HH:MM",
+        value_parser = QuietHours::parse,
+        help = "Do not track anything between these local times, e.g. 22:00-07:00. Can be repeated"
+    )]
+    quiet_hours: Vec<QuietHours>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Close the current event after this many polls in a row fail or find no window, instead of letting it run on"
+    )]
+    max_consecutive_errors: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Pause tracking while there is no interactive desktop, e.g. before logon when running as a service"
+    )]
+    foreground_only_when_focused: bool,
+
+    #[arg(long, num_args = 1.., value_delimiter = ',', help = "Comma-separated list of regex patterns that matches process names of transient windows, such as pickers, that should not split the current event")]
+    sticky_process: Vec<String>,
+
+    #[arg(long, num_args = 1.., value_delimiter = ',', help = "Comma-separated list of regex patterns that matches process names, such as games and media players, whose title changes should not split events. Their events keep the first title seen")]
+    stable_title_for: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Track the taskbar, Start menu and search like other windows instead of treating them as transient"
+    )]
+    track_shell: bool,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Only report a process not seen before once its window has kept the same title this long, e.g. 3s. Until then its time goes to the current event"
+    )]
+    new_app_grace: u32,
+
+    #[arg(
+        long,
+        default_value_t = 10000,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "How long a transient window may keep focus before it counts as a window change"
+    )]
+    sticky_max: u32,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "completed_events",
+        help = "On startup, continue the bucket's last event if the same window is still focused"
+    )]
+    continue_last_event: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Record a marker in a separate <bucket>-deep-focus bucket once the same window has been focused this long, e.g. 45m"
+    )]
+    deep_focus_threshold: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Close events at the moment the foreground changes or the focused window closes, instead of at the next poll"
+    )]
+    flush_on_focus_loss: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Poll as soon as the foreground changes, in addition to every --poll-time, so short visits to a window are not missed"
+    )]
+    event_driven: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Give the heartbeat that closes an event a duration covering the last poll interval"
+    )]
+    explicit_durations: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_millis,
+        help = "Round event timestamps down to a multiple of this duration, e.g. 1s"
+    )]
+    round_timestamps: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "explicit_durations",
+        help = "Send each event once, with its full duration, when it ends instead of sending heartbeats"
+    )]
+    completed_events: bool,
+
+    #[cfg(feature = "virtual-desktop")]
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Only track windows on the current virtual desktop and report its GUID as \"desktop\""
+    )]
+    include_virtual_desktop: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "hash_salt",
+        help = "Replace titles with a salted hash. This is irreversible"
+    )]
+    hash_titles: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "hash_salt",
+        help = "Replace app names with a salted hash. This is irreversible"
+    )]
+    hash_apps: bool,
+
+    #[arg(long, help = "Secret salt for --hash-titles and --hash-apps")]
+    hash_salt: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Keep at most this many failed heartbeats for retrying [default: no limit]"
+    )]
+    buffer_max: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Overflow::DropOldest,
+        help = "What to do when a heartbeat fails while --buffer-max heartbeats are already queued"
+    )]
+    buffer_overflow: Overflow,
+
+    #[arg(
+        long,
+        default_value_t = 0.5,
+        value_parser = parse_fraction,
+        help = "Randomly vary retry delays by up to this fraction (0.0-1.0) of their length"
+    )]
+    retry_jitter: f64,
+
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_fraction,
+        help = "Only report this fraction (0.0-1.0) of events, chosen at random"
+    )]
+    sample_rate: f64,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the logged-in user as \"user\""
+    )]
+    include_user: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report into a separate testing bucket on the testing server"
+    )]
+    testing: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the local UTC offset, e.g. +09:00, as \"tz\". Timestamps stay in UTC"
+    )]
+    include_timezone: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the version of the event data layout as \"schema\", for consumers to branch on"
+    )]
+    include_schema_version: bool,
+
+    #[cfg(feature = "uia")]
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the length (never the content) of the selected text as \"selection_len\". Splits events whenever the selection changes"
+    )]
+    capture_selection: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report dialogs and untitled popups as the window that owns them"
+    )]
+    resolve_owner: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report windows whose process cannot be queried with their window class as app, e.g. class:Chrome_WidgetWin_1"
+    )]
+    fallback_to_class: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report the product version of the app's executable as \"app_version\""
+    )]
+    include_app_version: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report whether the app runs elevated as \"elevated\". Left out when the process cannot be queried"
+    )]
+    include_elevation: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Guess whether the foreground window is receiving input and report it as \"active_input\". Splits events whenever the guess changes"
+    )]
+    include_active_input: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report whether the window is always on top, like picture-in-picture video, as \"topmost\""
+    )]
+    include_topmost: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report \"recording\": true while a screen recorder is running. Best-effort"
+    )]
+    detect_recording: bool,
+
+    #[arg(long, num_args = 1.., value_delimiter = ',', default_value = "obs(32|64)?\\.exe", help = "Comma-separated list of regex patterns that matches recorder process names for --detect-recording")]
+    recorder_processes: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Experimental: also report the topmost window of every monitor into a <bucket>-monitor-<display> bucket each. Walks all windows on every poll"
+    )]
+    track_all_monitors: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Also track the watcher's own windows, which are skipped by default"
+    )]
+    track_self: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print \"app | title\" to stdout whenever the window changes, e.g. for a status bar"
+    )]
+    emit_current: bool,
+
+    #[arg(
+        long,
+        hide = true,
+        value_name = "PER_SECOND",
+        value_parser = parse_rate,
+        help = "Testing tool: report made-up windows switching this many times per second on average instead of the real foreground window"
+    )]
+    simulate_switches: Option<f64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Do not send anything to aw-server. Events are still logged and sent to other outputs"
+    )]
+    dry_run: bool,
+
+    #[cfg(feature = "mqtt")]
+    #[arg(
+        long,
+        value_name = "HOST[:PORT]",
+        help = "Publish every new event to this MQTT broker"
+    )]
+    mqtt_broker: Option<String>,
+
+    #[cfg(feature = "mqtt")]
+    #[arg(
+        long,
+        default_value = "activitywatch/window",
+        help = "The MQTT topic to publish events to"
+    )]
+    mqtt_topic: String,
+
+    #[cfg(feature = "webhook")]
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "POST every finished event to this URL as JSON"
+    )]
+    webhook_url: Option<String>,
+
+    #[cfg(feature = "sqlite")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write events to this SQLite database, creating it if needed"
+    )]
+    sqlite: Option<PathBuf>,
+}
+
+/// Every field the watcher can report.
+const FIELDS: &[&str] = &[
+    "app",
+    "title",
+    "tab",
+    "desktop",
+    "selection_len",
+    "user",
+    "app_version",
+    "recording",
+    "tz",
+    "label",
+    "schema",
+    "title_redacted",
+    "monitor",
+    "elevated",
+    "active_input",
+    "topmost",
+    "path",
+];
+
+impl Args {
+    /// Takes the options that are only read at startup from `startup`, so
+    /// they stay consistent with the buckets, connections and outputs set up
+    /// then. Returns the ones that `self` tried to change.
+    fn keep_startup_options(&mut self, startup: &Args) -> Vec<String> {
+        let mut kept = Vec::new();
+        macro_rules! keep {
+            ($($option:ident),* $(,)?) => {
+                $(
+                    if self.$option != startup.$option {
+                        kept.push(format!("--{}", stringify!($option).replace('_', "-")));
+                        self.$option = startup.$option.clone();
+                    }
+                )*
+            };
+        }
+        keep!(
+            host,
+            port,
+            testing,
+            bucket_name,
+            route,
+            liveness_bucket,
+            dry_run,
+            debug,
+            emit_current,
+            event_driven,
+            flush_on_focus_loss,
+            buffer_max,
+            buffer_overflow,
+            shutdown_timeout,
+        );
+        #[cfg(feature = "mqtt")]
+        keep!(mqtt_broker, mqtt_topic);
+        #[cfg(feature = "webhook")]
+        keep!(webhook_url);
+        #[cfg(feature = "sqlite")]
+        keep!(sqlite);
+        // Their buckets are only created at startup, but the thresholds
+        // themselves are read on every poll.
+        if self.deep_focus_threshold.is_some() != startup.deep_focus_threshold.is_some() {
+            kept.push("--deep-focus-threshold".to_string());
+            self.deep_focus_threshold = startup.deep_focus_threshold;
+        }
+        if self.afk_timeout.is_some() != startup.afk_timeout.is_some() {
+            kept.push("--afk-timeout".to_string());
+            self.afk_timeout = startup.afk_timeout;
+        }
+        kept
+    }
+
+    /// Returns whether `field` may be sent under --fields.
+    fn sends(&self, field: &str) -> bool {
+        match &self.fields {
+            Some(fields) => fields.iter().any(|f| f == field),
+            None => true,
+        }
+    }
+
+    /// Whether any option needs UI Automation.
+    #[cfg(feature = "uia")]
+    fn uses_uia(&self) -> bool {
+        !self.uia_options().is_empty()
+    }
+
+    /// The options given that need UI Automation, as they are written on
+    /// the command line.
+    fn uia_options(&self) -> Vec<&'static str> {
+        #[cfg(not(feature = "uia"))]
+        let options = Vec::new();
+        #[cfg(feature = "uia")]
+        let options = [
+            (self.terminal_tabs, "--terminal-tabs"),
+            (self.capture_selection, "--capture-selection"),
+        ]
+        .into_iter()
+        .filter_map(|(given, option)| given.then_some(option))
+        .chain(self.title_sources.iter().filter_map(|source| match source {
+            TitleSource::Uia => Some("--title-sources uia"),
+            TitleSource::Msaa => Some("--title-sources msaa"),
+            _ => None,
+        }))
+        .collect();
+        options
+    }
+
+    /// Whether --title-sources needs UI Automation or MSAA.
+    #[cfg(not(feature = "uia"))]
+    fn reads_accessibility_titles(&self) -> bool {
+        self.title_sources
+            .iter()
+            .any(|source| matches!(source, TitleSource::Uia | TitleSource::Msaa))
+    }
+
+    /// The length of the --sample-window or --coalesce-app-window windows.
+    fn window(&self) -> Option<u32> {
+        self.sample_window.or(self.coalesce_app_window)
+    }
+
+    /// The pulsetime in seconds: how far apart two heartbeats with the same
+    /// data may be and still be merged into one event.
+    fn pulsetime(&self) -> f64 {
+        let millis = self
+            .pulsetime
+            .unwrap_or_else(|| self.heartbeat_interval().saturating_add(1000));
+        f64::from(millis) / 1000.0
+    }
+
+    /// The longest time between two heartbeats for the same window.
+    fn heartbeat_interval(&self) -> u32 {
+        let interval = self.window().unwrap_or(self.poll_time);
+        if self.change_only_heartbeats {
+            // The keepalive is checked on polls, so it can be a poll late.
+            self.keepalive_interval
+                .max(interval)
+                .saturating_add(self.poll_time)
+        } else {
+            interval
+        }
+    }
+
+    /// How long requests from the poll loop may take. Under the watchdog, it
+    /// defaults to half the watchdog timeout, so a hung server fails the
+    /// request instead of having the worker restarted over and over, each
+    /// time leaving a blocked thread behind.
+    fn request_timeout(&self) -> Option<u32> {
+        self.request_timeout.or_else(|| {
+            self.watchdog_timeout
+                .filter(|_| !self.completed_events)
+                .map(|timeout| timeout / 2)
+        })
+    }
+
+    /// The server port, defaulting to the testing server's port in testing
+    /// mode like the other ActivityWatch watchers.
+    fn port(&self) -> u16 {
+        match self.port {
+            Some(port) => port,
+            None if self.testing => 5666,
+            None => 5600,
+        }
+    }
+}
+
+/// Which name is reported as an event's app.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum AppSource {
+    /// The executable name, such as Code.exe
+    Exe,
+    /// The file description from the executable's version resource, such
+    /// as Visual Studio Code
+    Description,
+    /// The AppUserModelID of packaged (Store) apps
+    Aumid,
+}
+
+/// What tells two apps apart when deciding whether the window changed.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum DedupBy {
+    /// The executable name
+    App,
+    /// The full executable path, so same-named executables from different
+    /// installs count as different apps
+    Path,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print version and build information as JSON
+    Version,
+    /// Check the arguments and patterns without connecting to the server or
+    /// tracking windows, exiting with an error if any pattern is invalid
+    Validate,
+    /// Print how long each app was active recently, based on the bucket
+    /// and its --route buckets
+    Report {
+        #[arg(
+            long,
+            default_value = "1h",
+            value_name = "DURATION",
+            value_parser = parse_millis,
+            help = "How far back to report, e.g. 30m or 8h"
+        )]
+        since: u32,
+    },
+    /// Print the events of the bucket and its --route buckets, or those in
+    /// the --sqlite database if given
+    Export {
+        #[arg(long, value_enum, default_value_t = export::Format::Jsonl)]
+        format: export::Format,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_millis,
+            help = "Only export events from this far back, e.g. 30m or 8h [default: all events]"
+        )]
+        since: Option<u32>,
+    },
+    /// Report windows recorded in a file instead of the real foreground
+    /// window, at the pace they were recorded, then exit
+    Replay {
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "JSON lines with a timestamp, app and title each, such as the output of export"
+        )]
+        input: PathBuf,
+    },
+}
+
+fn main() {
+    console::attach_parent();
+    let matches = parse_args();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    log::init(args.debug, args.emit_current);
+    if let Some(Command::Version) = args.command {
+        print_version();
+        return;
+    }
+    if args.list_windows {
+        list_windows();
+        return;
+    }
+    if args.print_config {
+        print_config(&args, &matches);
+        return;
+    }
+    if let Some(timeout) = args.watchdog_timeout {
+        // Polls pause twice while no window has focus.
+        if timeout <= args.poll_time.saturating_mul(2) {
+            Args::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "--watchdog-timeout must be longer than twice --poll-time",
+                )
+                .exit();
+        }
+    }
+
+    if args.round_timestamps == Some(0) {
+        Args::command()
+            .error(
+                ErrorKind::ValueValidation,
+                "--round-timestamps must be longer than 0ms",
+            )
+            .exit();
+    }
+
+    if let Some(window) = args.window() {
+        if window < args.poll_time {
+            Args::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "--sample-window and --coalesce-app-window must not be shorter than --poll-time",
+                )
+                .exit();
+        }
+    }
+
+    if let (Some(request), Some(watchdog)) = (args.request_timeout, args.watchdog_timeout) {
+        if request >= watchdog {
+            warn!("--request-timeout is not shorter than --watchdog-timeout, so a hung request restarts the poll worker, which then waits for the request as well");
+        }
+    }
+    if args.watchdog_timeout.is_some() && args.request_timeout().is_none() {
+        warn!("--completed-events leaves requests without a timeout, so under --watchdog-timeout a hung aw-server restarts the poll worker again and again, each time leaving a blocked request behind");
+    }
+    if args.buffer_overflow == Overflow::Block && args.watchdog_timeout.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--buffer-overflow block pauses polling, which --watchdog-timeout would take for a hang",
+            )
+            .exit();
+    }
+
+    // The path to compare against is read back from the last event.
+    if args.continue_last_event
+        && args.dedup_by == DedupBy::Path
+        && !(args.include_path && args.sends("path"))
+    {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--continue-last-event with --dedup-by path needs --include-path, so that events record the path",
+            )
+            .exit();
+    }
+
+    if !args.keep_title_for.is_empty() && !args.exclude_title && args.redact_title_for.is_empty() {
+        warn!("--keep-title-for only makes exceptions to --exclude-title and --redact-title-for, and neither is given. It has no effect");
+    }
+    if let Some(fields) = &args.fields {
+        for field in fields.iter().filter(|f| !FIELDS.contains(&f.as_str())) {
+            warn!(
+                "--fields lists '{}', which this watcher never sends. Known fields are {}",
+                field,
+                FIELDS.join(", ")
+            );
+        }
+        if !args.sends("app") {
+            warn!("--fields does not include app. Events will not say which app was used");
+        }
+    }
+    if args
+        .pulsetime
+        .is_some_and(|pulsetime| pulsetime < args.heartbeat_interval())
+    {
+        warn!("--pulsetime is shorter than the time between heartbeats, so events will be split into one per heartbeat");
+    }
+    if args.pulsetime.is_none() && args.pulsetime() > LONG_PULSETIME_SECS {
+        warn!(
+            "Heartbeats up to {}s apart will be merged, which can join separate sessions into one event. Pass --pulsetime to merge over shorter gaps",
+            args.pulsetime()
+        );
+    }
+
+    if let Some(Command::Validate) = args.command {
+        validate(&args);
+        return;
+    }
+
+    let host_suffix = bucket_suffix();
+    let window_bucket = match &args.bucket_name {
+        Some(name) => name.clone(),
+        None if args.testing => format!("aw-watcher-window-rs-testing_{}", host_suffix),
+        None => format!("aw-watcher-window-rs_{}", host_suffix),
+    };
+    let client = new_client(&args);
+    // Apps sent to --route buckets are reported and exported too.
+    let window_buckets: Vec<String> = std::iter::once(window_bucket.clone())
+        .chain(
+            args.route
+                .iter()
+                .map(|(_, suffix)| route_bucket(&window_bucket, suffix)),
+        )
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect();
+    if let Some(Command::Report { since }) = args.command {
+        if let Err(e) = check_server(&client, &args) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        let since = Duration::milliseconds(since.into());
+        if let Err(e) = report::print(&client, &window_buckets, since) {
+            error!("Failed to read events: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(feature = "sqlite")]
+    let sqlite = args
+        .sqlite
+        .as_ref()
+        .map(|path| match sqlite::Store::open(path) {
+            Ok(store) => store,
+            Err(e) => {
+                error!("Failed to open {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        });
+    if let Some(Command::Export { format, since }) = args.command {
+        let since = since.map(|since| Utc::now() - Duration::milliseconds(since.into()));
+        #[cfg(feature = "sqlite")]
+        let stored = sqlite.as_ref().map(|store| store.events(since));
+        #[cfg(not(feature = "sqlite"))]
+        let stored: Option<Result<_, WatcherError>> = None;
+        let events = match stored {
+            Some(Ok(events)) => events,
+            Some(Err(e)) => {
+                error!("Failed to read events: {}", e);
+                std::process::exit(1);
+            }
+            None => {
+                if let Err(e) = check_server(&client, &args) {
+                    error!("{}", e);
+                    std::process::exit(1);
+                }
+                match export::from_server(&client, &window_buckets, since) {
+                    Ok(events) => events,
+                    Err(e) => {
+                        error!("Failed to read events: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        };
+        if let Err(e) = export::write(&events, format, &mut std::io::stdout().lock()) {
+            error!("Failed to export events: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(rate) = args.simulate_switches {
+        warn!(
+            "Simulating {} window switches per second. Real windows are not tracked",
+            rate
+        );
+    }
+    let replay = match &args.command {
+        Some(Command::Replay { input }) => {
+            if args.simulate_switches.is_some() {
+                Args::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "replay cannot be combined with --simulate-switches",
+                    )
+                    .exit();
+            }
+            let rows = std::fs::File::open(input)
+                .map_err(|e| e.to_string())
+                .and_then(|file| replay::read_rows(std::io::BufReader::new(file)));
+            match rows {
+                Ok(rows) if rows.is_empty() => {
+                    error!("{} has no windows to replay", input.display());
+                    std::process::exit(1);
+                }
+                Ok(rows) => {
+                    warn!(
+                        "Replaying {} windows from {}. Real windows are not tracked",
+                        rows.len(),
+                        input.display()
+                    );
+                    Some(rows)
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", input.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+    #[cfg(not(feature = "uia"))]
+    if args.reads_accessibility_titles() {
+        warn!("--title-sources lists uia or msaa, which need the uia feature. They are skipped");
+    }
+    let watcher = Watcher {
+        capabilities: capability::probe(&args.uia_options()),
+        replay,
+        #[cfg(feature = "sqlite")]
+        sqlite,
+        #[cfg(feature = "mqtt")]
+        mqtt: args
+            .mqtt_broker
+            .as_ref()
+            .map(|broker| mqtt::Publisher::connect(broker, &args.mqtt_topic)),
+        #[cfg(feature = "webhook")]
+        webhook: args
+            .webhook_url
+            .as_ref()
+            .map(|url| webhook::Webhook::new(url, args.retry_jitter)),
+        title_rules: TitleRules::new(
+            args.exclude_title,
+            &args.redact_title_for,
+            &args.keep_title_for,
+            args.substring_regex,
+        ),
+        title_ignore: rules::compile_patterns(&args.title_ignore_pattern, true),
+        path_patterns: if args.scrub_paths {
+            rules::path_patterns()
+        } else {
+            Vec::new()
+        },
+        recorders: rules::compile_patterns(&args.recorder_processes, args.substring_regex),
+        sticky: rules::compile_patterns(&args.sticky_process, args.substring_regex),
+        stable_title: rules::compile_patterns(&args.stable_title_for, args.substring_regex),
+        deep_focus_bucket: args
+            .deep_focus_threshold
+            .map(|_| format!("{}-deep-focus", window_bucket)),
+        afk_bucket: args
+            .afk_timeout
+            .map(|_| format!("aw-watcher-afk_{}", host_suffix)),
+        routes: args
+            .route
+            .iter()
+            .map(|(pattern, suffix)| {
+                let regex =
+                    rules::compile_patterns(std::slice::from_ref(pattern), args.substring_regex);
+                (regex, route_bucket(&window_bucket, suffix))
+            })
+            .collect(),
+        window_bucket,
+        args,
+    };
+
+    thread::sleep(time::Duration::from_millis(
+        watcher.args.startup_delay.into(),
+    ));
+
+    // AwClient::new does not connect, so without this check a wrong host
+    // or port would only show up as bucket creation failing over and over.
+    let mut delay = time::Duration::from_secs(1);
+    if !watcher.args.dry_run {
+        while let Err(e) = check_server(&client, &watcher.args) {
+            warn!("{}. Retrying in {}s...", e, delay.as_secs());
+            thread::sleep(jittered(delay, watcher.args.retry_jitter));
+            delay = (delay * 2).min(MAX_CONNECT_BACKOFF);
+        }
+    }
+
+    let buckets = std::iter::once(&watcher.window_bucket)
+        .chain(watcher.routes.iter().map(|(_, bucket)| bucket))
+        .map(|bucket| (bucket, "currentwindow"))
+        .chain(
+            watcher
+                .args
+                .liveness_bucket
+                .iter()
+                .map(|bucket| (bucket, LIVENESS_BUCKET_TYPE)),
+        )
+        .chain(
+            watcher
+                .deep_focus_bucket
+                .iter()
+                .map(|bucket| (bucket, DEEP_FOCUS_BUCKET_TYPE)),
+        )
+        .chain(
+            watcher
+                .afk_bucket
+                .iter()
+                .map(|bucket| (bucket, AFK_BUCKET_TYPE)),
+        );
+    for (bucket, bucket_type) in buckets {
+        if watcher.args.dry_run {
+            break;
+        }
+        loop {
+            match client
+                .create_bucket_simple(bucket, bucket_type)
+                .map_err(WatcherError::from)
+            {
+                Ok(_) => break,
+                Err(e) if e.is_already_exists() => {
+                    debug!("Bucket {} already exists", bucket);
+                    break;
+                }
+                Err(e) => {
+                    warn!("Failed to create bucket {}: {}. Retrying...", bucket, e);
+                    thread::sleep(jittered(
+                        time::Duration::from_millis(1000),
+                        watcher.args.retry_jitter,
+                    ));
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "uia")]
+    if watcher.args.capture_selection {
+        use std::io::IsTerminal;
+        warn!("**************************************************************");
+        warn!("--capture-selection is enabled. The length of any text you");
+        warn!("select is recorded. The text itself is never read.");
+        warn!("**************************************************************");
+        // Without a console, as when started at login, nobody sees the log.
+        if !std::io::stderr().is_terminal() {
+            thread::spawn(|| {
+                let _ = HWND::NULL.MessageBox(
+                    "--capture-selection is enabled. The length of any text you select is recorded. The text itself is never read.",
+                    "aw-watcher-window-rs",
+                    co::MB::ICONWARNING,
+                );
+            });
+        }
+    }
+
+    if watcher.args.flush_on_focus_loss || watcher.args.event_driven {
+        foreground::spawn_hook();
+    }
+    shutdown::install_handler(time::Duration::from_millis(
+        watcher
+            .args
+            .poll_time
+            .saturating_add(watcher.args.shutdown_timeout)
+            .into(),
+    ));
+
+    // Keeps the config file watched for as long as polling runs.
+    let _config_watcher = match config_path(&matches) {
+        Some(path) if watcher.args.watchdog_timeout.is_none() => match config::watch(&path) {
+            Ok(config_watcher) => Some(config_watcher),
+            Err(e) => {
+                warn!("Failed to watch {} for changes: {}", path.display(), e);
+                None
+            }
+        },
+        Some(_) => {
+            info!("--watchdog-timeout is set, so changes to the config file need a restart");
+            None
+        }
+        None => None,
+    };
+
+    match watcher.args.watchdog_timeout {
+        Some(timeout) => {
+            let watcher = Arc::new(watcher);
+            let retry_queue: Arc<RetryQueue> = Arc::new(Mutex::new(Buffer::new(
+                watcher.args.buffer_max,
+                watcher.args.buffer_overflow,
+            )));
+            watchdog::supervise(timeout, move |liveness, generation| {
+                let client = new_client(&watcher.args);
+                watch(&watcher, &client, liveness, generation, &retry_queue);
+            });
+        }
+        None => {
+            let mut watcher = watcher;
+            let retry_queue = Mutex::new(Buffer::new(
+                watcher.args.buffer_max,
+                watcher.args.buffer_overflow,
+            ));
+            loop {
+                watch(&watcher, &client, &Liveness::new(), 0, &retry_queue);
+                if shutdown::requested() || !config::take_change() {
+                    break;
+                }
+                match reparse_args() {
+                    Ok(args) => {
+                        info!("The config file changed. Applying it");
+                        watcher = watcher.reload(args);
+                    }
+                    Err(e) => error!("Ignoring the changed config file: {}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Where made-up windows come from instead of the real foreground window.
+enum Synthetic {
+    /// Random switches from --simulate-switches.
+    Simulated(Simulator),
+    /// Recorded windows from `replay`.
+    Replayed(Replay),
+}
+
+impl Synthetic {
+    /// Returns the process id, process name and title of the foreground
+    /// window for a poll `poll_ms` after the previous one.
+    fn poll(&mut self, poll_ms: u32) -> Option<(u32, String, String)> {
+        match self {
+            Synthetic::Simulated(simulator) => Some(simulator.poll(poll_ms)),
+            Synthetic::Replayed(replay) => replay.poll(Utc::now()),
+        }
+    }
+
+    /// Returns whether there are no more windows to report.
+    fn finished(&self) -> bool {
+        match self {
+            Synthetic::Simulated(_) => false,
+            Synthetic::Replayed(replay) => replay.finished(),
+        }
+    }
+}
+
+/// Settings shared by every poll worker. Without --watchdog-timeout, they
+/// are replaced when the config file changes.
+struct Watcher {
+    args: Args,
+    capabilities: Capabilities,
+    window_bucket: String,
+    title_rules: TitleRules,
+    title_ignore: Vec<Regex>,
+    /// Empty unless --scrub-paths is given.
+    path_patterns: Vec<(Regex, &'static str)>,
+    recorders: Vec<Regex>,
+    sticky: Vec<Regex>,
+    stable_title: Vec<Regex>,
+    /// Buckets for apps matching each pattern, checked in order before
+    /// falling back to `window_bucket`.
+    routes: Vec<(Vec<Regex>, String)>,
+    /// Where --deep-focus-threshold markers go.
+    deep_focus_bucket: Option<String>,
+    /// Where --afk-timeout reports AFK status.
+    afk_bucket: Option<String>,
+    /// The windows to report instead of real ones under `replay`.
+    replay: Option<Vec<replay::Row>>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<mqtt::Publisher>,
+    #[cfg(feature = "webhook")]
+    webhook: Option<webhook::Webhook>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<sqlite::Store>,
+}
+
+impl Watcher {
+    /// Switches to `args` from a changed config file. Rules and the options
+    /// read on every poll follow it. Buckets, connections and outputs stay
+    /// as they were set up at startup, and so do the options they came from.
+    fn reload(self, mut args: Args) -> Watcher {
+        let kept = args.keep_startup_options(&self.args);
+        if !kept.is_empty() {
+            warn!(
+                "Changes to {} need a restart. They are ignored until then",
+                kept.join(", ")
+            );
+        }
+        Watcher {
+            capabilities: capability::probe(&args.uia_options()),
+            title_rules: TitleRules::new(
+                args.exclude_title,
+                &args.redact_title_for,
+                &args.keep_title_for,
+                args.substring_regex,
+            ),
+            title_ignore: rules::compile_patterns(&args.title_ignore_pattern, true),
+            path_patterns: if args.scrub_paths {
+                rules::path_patterns()
+            } else {
+                Vec::new()
+            },
+            recorders: rules::compile_patterns(&args.recorder_processes, args.substring_regex),
+            sticky: rules::compile_patterns(&args.sticky_process, args.substring_regex),
+            stable_title: rules::compile_patterns(&args.stable_title_for, args.substring_regex),
+            args,
+            ..self
+        }
+    }
+
+    /// Returns the bucket that events of `app` are sent to.
+    fn bucket_for(&self, app: &str) -> &str {
+        self.routes
+            .iter()
+            .find(|(regex, _)| regex.iter().any(|r| r.is_match(app)))
+            .map_or(&self.window_bucket, |(_, bucket)| bucket)
+    }
+}
+
+/// Returns the hostname lowercased, with characters that are not safe in a
+/// bucket id replaced. Dots are kept so existing buckets keep their ids.
+fn bucket_suffix() -> String {
+    let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+    let sanitized = hostname
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    if sanitized != hostname {
+        info!(
+            "Hostname '{}' is not a valid bucket id suffix. Using '{}' instead",
+            hostname, sanitized
+        );
+    }
+    sanitized
+}
+
+/// Returns the bucket that `--route` sends matching apps to.
+fn route_bucket(window_bucket: &str, suffix: &str) -> String {
+    format!("{}-{}", window_bucket, suffix)
+}
+
+fn parse_route(s: &str) -> Result<(String, String), String> {
+    match s.rsplit_once('=') {
+        Some((pattern, suffix)) if !pattern.is_empty() && !suffix.is_empty() => {
+            Ok((pattern.to_string(), suffix.to_string()))
+        }
+        _ => Err("expected REGEX=SUFFIX".to_string()),
+    }
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let value = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} is not between 0.0 and 1.0", value))
+    }
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let value = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if value.is_finite() && value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{} is not a finite, non-negative rate", value))
+    }
+}
+
+/// Randomly stretches or shrinks `delay` by up to `jitter` of its length so
+/// that watchers restarted together do not retry in lockstep.
+fn jittered(delay: time::Duration, jitter: f64) -> time::Duration {
+    if jitter == 0.0 {
+        return delay;
+    }
+    delay.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+}
+
+fn new_client(args: &Args) -> Arc<aw_client_rust::blocking::AwClient> {
+    Arc::new(
+        aw_client_rust::blocking::AwClient::new(&args.host, args.port(), "aw-watcher-window-rs")
+            .expect("Failed to create a client"),
+    )
+}
+
+/// Makes a request to aw-server from the poll loop. With --request-timeout,
+/// it runs on a thread of its own and is given up on once the timeout has
+/// passed, so a hung request cannot stall polling. The abandoned request
+/// cannot be cancelled and may still complete later, so callers must not
+/// retry it, and later requests fail with `WatcherError::Busy` until it has
+/// finished.
+fn request<T: Send + 'static>(
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    args: &Args,
+    f: impl FnOnce(&aw_client_rust::blocking::AwClient) -> Result<T, reqwest::Error> + Send + 'static,
+) -> Result<T, WatcherError> {
+    let timeout = match args.request_timeout() {
+        Some(timeout) => timeout,
+        None => return Ok(f(client.as_ref())?),
+    };
+    let mut abandoned = ABANDONED.lock().unwrap();
+    if abandoned
+        .as_ref()
+        .is_some_and(|thread| !thread.is_finished())
+    {
+        return Err(WatcherError::Busy);
+    }
+    let (sender, receiver) = mpsc::channel();
+    let client = Arc::clone(client);
+    let thread = thread::spawn(move || {
+        // The receiver is gone if the request timed out.
+        let _ = sender.send(f(client.as_ref()));
+    });
+    match receiver.recv_timeout(time::Duration::from_millis(timeout.into())) {
+        Ok(result) => Ok(result?),
+        Err(_) => {
+            *abandoned = Some(thread);
+            Err(WatcherError::TimedOut(timeout))
+        }
+    }
+}
+
+/// Checks that aw-server answers at the configured address, connecting
+/// with a short timeout first so an unreachable host fails quickly.
+fn check_server(client: &aw_client_rust::blocking::AwClient, args: &Args) -> Result<(), String> {
+    let address = format!("{}:{}", args.host, args.port());
+    let unreachable = |reason: &dyn std::fmt::Display| {
+        format!("aw-server unreachable at {}: {}", address, reason)
+    };
+    let mut connected = Err(unreachable(&"the host has no address"));
+    for socket in address.to_socket_addrs().map_err(|e| unreachable(&e))? {
+        match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+            Ok(_) => {
+                connected = Ok(());
+                break;
+            }
+            Err(e) => connected = Err(unreachable(&e)),
+        }
+    }
+    connected?;
+    client.get_info().map_err(|e| {
+        format!(
+            "No aw-server answering at {}: {}",
+            address,
+            WatcherError::from(e)
+        )
+    })?;
+    Ok(())
+}
+
+/// Polls the foreground window and reports it until the profiling period
+/// ends or a watchdog replaces this worker.
+fn watch(
+    watcher: &Watcher,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    liveness: &Liveness,
+    generation: u64,
+    retry_queue: &RetryQueue,
+) {
+    let args = &watcher.args;
+    let mut prev_bucket = watcher.window_bucket.as_str();
+    let mut prev_data = Map::new();
+    let mut prev_key = Map::new();
+    let mut last_sent = DateTime::<Utc>::MIN_UTC;
+    let mut sampled = true;
+    let mut has_desktop = true;
+    let mut deep_focus_marked = false;
+    let mut in_quiet_hours = false;
+    let mut sticky_since = None;
+    let mut seen_processes: PerProcess<()> = PerProcess::default();
+    let mut new_app = None;
+    let mut last_alive: Option<time::Instant> = None;
+    let mut app_versions: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut descriptions: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut app_ids: PerProcess<Option<String>> = PerProcess::default();
+    let mut monitor_buckets = HashSet::new();
+    let mut failed_polls = 0;
+    let mut simulator = match &watcher.replay {
+        Some(rows) => Some(Synthetic::Replayed(Replay::new(rows.clone()))),
+        None => args
+            .simulate_switches
+            .map(|rate| Synthetic::Simulated(Simulator::new(rate))),
+    };
+    let mut afk = args
+        .afk_timeout
+        .map(|timeout| Afk::new(Duration::milliseconds(timeout.into())));
+    let mut elevations: PerProcess<Option<bool>> = PerProcess::default();
+    let mut users = user::Users::new();
+    let mut event_start = last_sent;
+    if args.continue_last_event {
+        if let Some((bucket, event)) = last_event(watcher, client) {
+            debug!("Continuing the last event in {}", bucket);
+            prev_key = change_key(watcher, &event.data);
+            prev_bucket = bucket;
+            event_start = event.timestamp;
+            last_sent = event.timestamp + event.duration;
+            prev_data = event.data;
+        }
+    }
+    // When the last heartbeat for the current window was actually sent,
+    // which lags behind last_sent under --change-only-heartbeats.
+    let mut last_beat = last_sent;
+    let mut profiler = args.profile.map(Profiler::new);
+    let mut sampler = args.sample_window.map(Sampler::new);
+    let mut coalescer = args.coalesce_app_window.map(Coalescer::new);
+    #[cfg(feature = "uia")]
+    let uia = if args.uses_uia() && watcher.capabilities.uia {
+        match Uia::new() {
+            Ok(uia) => Some(uia),
+            Err(e) => {
+                error!("Failed to initialize UI Automation: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg(feature = "virtual-desktop")]
+    let desktops = if args.include_virtual_desktop {
+        match VirtualDesktops::new() {
+            Ok(desktops) => Some(desktops),
+            Err(e) => {
+                error!("Failed to access virtual desktops: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    loop {
+        pause(args);
+        if !liveness.beat(generation) {
+            return;
+        }
+        if let Some(p) = &mut profiler {
+            if p.is_finished() {
+                p.print_report();
+                return;
+            }
+            p.record_poll();
+        }
+        flush_retry_queue(&mut lock(retry_queue), client, args);
+        if let Some(bucket) = &args.liveness_bucket {
+            if last_alive.is_none_or(|alive| alive.elapsed() >= LIVENESS_INTERVAL) {
+                report_alive(client, bucket, args);
+                last_alive = Some(time::Instant::now());
+            }
+        }
+
+        if args.foreground_only_when_focused {
+            let available = session::has_input_desktop();
+            if available != has_desktop {
+                has_desktop = available;
+                if available {
+                    info!("An interactive desktop is available. Resuming tracking");
+                } else {
+                    info!("No interactive desktop is available. Pausing tracking");
+                }
+            }
+            if !available {
+                prev_data.clear();
+                prev_key.clear();
+                continue;
+            }
+        }
+
+        let local_time = Local::now().time();
+        let quiet = args
+            .quiet_hours
+            .iter()
+            .any(|hours| hours.contains(local_time));
+        if quiet != in_quiet_hours {
+            in_quiet_hours = quiet;
+            if quiet {
+                info!("Entering quiet hours. Pausing tracking");
+            } else {
+                info!("Leaving quiet hours. Resuming tracking");
+            }
+        }
+
+        let afk_bucket = watcher.afk_bucket.as_ref().filter(|_| !quiet);
+        if let (Some(afk), Some(bucket)) = (&mut afk, afk_bucket) {
+            report_afk(afk, client, bucket, args);
+        }
+        if args.track_all_monitors && !quiet {
+            report_monitors(watcher, client, &mut monitor_buckets, &mut profiler);
+        }
+
+        // Once Ctrl+C is pressed, the current event is closed as if focus
+        // was lost and the process exits after the queue is flushed. A
+        // finished replay does the same, and a changed config file as well
+        // before polling resumes with it.
+        let stopping = shutdown::requested()
+            || config::changed()
+            || simulator.as_ref().is_some_and(Synthetic::finished);
+        let foreground = if quiet || stopping {
+            None
+        } else if simulator.is_some() {
+            Some(HWND::NULL)
+        } else {
+            timed(
+                &mut profiler,
+                "GetForegroundWindow",
+                HWND::GetForegroundWindow,
+            )
+        };
+        // After too many failed polls in a row the current event is closed
+        // at the last successful poll, so the failures show up as a gap.
+        let failing = args
+            .max_consecutive_errors
+            .is_some_and(|max| failed_polls >= max)
+            && !prev_data.is_empty();
+        let active_window = match foreground {
+            Some(hwnd) if !failing => hwnd,
+            foreground => {
+                if failing {
+                    info!(
+                        "{} polls in a row failed. Closing the current event",
+                        failed_polls
+                    );
+                } else if !quiet && !stopping {
+                    debug!("No active window found");
+                }
+                if foreground.is_none() && !quiet && !stopping {
+                    failed_polls += 1;
+                }
+                if (args.flush_on_focus_loss || quiet || failing || stopping)
+                    && !prev_data.is_empty()
+                {
+                    prev_key.clear();
+                    let closing_data = std::mem::take(&mut prev_data);
+                    if sampled {
+                        let closed_at = if failing {
+                            fence(last_sent)
+                        } else {
+                            switch_time(last_sent, args).max(fence(last_sent))
+                        };
+                        #[cfg(feature = "webhook")]
+                        finish(watcher, &closing_data, event_start, closed_at, prev_bucket);
+                        let closing = closing_event(
+                            closing_data,
+                            event_start,
+                            last_sent,
+                            closed_at,
+                            prev_bucket,
+                            args,
+                        );
+                        timed(&mut profiler, "heartbeat", || {
+                            send_or_queue(
+                                &mut lock(retry_queue),
+                                closing,
+                                client,
+                                prev_bucket,
+                                watcher,
+                            )
+                        });
+                    }
+                }
+                if stopping {
+                    flush_retry_queue(&mut lock(retry_queue), client, args);
+                    if shutdown::requested() {
+                        shutdown::finish();
+                    }
+                    return;
+                }
+                pause(args);
+                continue;
+            }
+        };
+        let (
+            active_window,
+            desktop,
+            process_id,
+            process_path,
+            process_name,
+            transient,
+            window_title,
+        ) = match &mut simulator {
+            Some(simulator) => {
+                let Some((process_id, process_name, window_title)) = simulator.poll(args.poll_time)
+                else {
+                    continue;
+                };
+                (
+                    None,
+                    None,
+                    process_id,
+                    None,
+                    process_name,
+                    false,
+                    window_title,
+                )
+            }
+            None => {
+                let active_window = if args.resolve_owner {
+                    resolve_owner(active_window)
+                } else {
+                    active_window
+                };
+                if args.flush_on_focus_loss {
+                    foreground::track(&active_window);
+                }
+                #[cfg(feature = "virtual-desktop")]
+                let desktop: Option<String> = match &desktops {
+                    Some(desktops) => match desktops.is_on_current(&active_window) {
+                        Ok(true) => match desktops.desktop_id(&active_window) {
+                            Ok(id) => Some(id),
+                            Err(e) => {
+                                error!("Failed to get virtual desktop id: {}", e);
+                                None
+                            }
+                        },
+                        Ok(false) => {
+                            debug!("Foreground window is not on the current virtual desktop");
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to check virtual desktop: {}", e);
+                            None
+                        }
+                    },
+                    None => None,
+                };
+                #[cfg(not(feature = "virtual-desktop"))]
+                let desktop: Option<String> = None;
+
+                let (_, process_id) = timed(&mut profiler, "GetWindowThreadProcessId", || {
+                    active_window.GetWindowThreadProcessId()
+                });
+                match process::owner(process_id, std::process::id()) {
+                    Owner::Nobody => {
+                        debug!(
+                            "The foreground window has no process, likely because it is closing"
+                        );
+                        failed_polls += 1;
+                        continue;
+                    }
+                    Owner::Watcher if !args.track_self => {
+                        debug!("Skipping the watcher's own window");
+                        continue;
+                    }
+                    Owner::Watcher | Owner::Process(_) => {}
+                }
+
+                let process_path = if watcher.capabilities.process_query {
+                    query_process_path(&mut profiler, process_id)
+                } else {
+                    None
+                };
+                let process_name = match process_path.as_deref().and_then(executable_name) {
+                    Some(name) => name,
+                    None if args.fallback_to_class || !watcher.capabilities.process_query => {
+                        match active_window.GetClassName() {
+                            Ok(class) => format!("class:{}", class),
+                            Err(e) => {
+                                error!("Failed to get window class: {}", e);
+                                failed_polls += 1;
+                                continue;
+                            }
+                        }
+                    }
+                    None => {
+                        failed_polls += 1;
+                        continue;
+                    }
+                };
+
+                // A transient window only briefly takes focus, so its time is given
+                // to the event it interrupted instead of splitting that event.
+                let transient = if watcher.sticky.iter().any(|r| r.is_match(&process_name))
+                    || (!args.track_shell && is_shell_window(&active_window, &process_name))
+                {
+                    let since = *sticky_since.get_or_insert_with(Utc::now);
+                    Utc::now() - since < Duration::milliseconds(args.sticky_max.into())
+                } else {
+                    sticky_since = None;
+                    false
+                };
+
+                let window_title = match read_title(
+                    &active_window,
+                    &args.title_sources,
+                    #[cfg(feature = "uia")]
+                    uia.as_ref(),
+                    &mut profiler,
+                ) {
+                    Ok(title) => title,
+                    Err(e) => {
+                        error!("Failed to get window title: {}", e);
+                        failed_polls += 1;
+                        continue;
+                    }
+                };
+                (
+                    Some(active_window),
+                    desktop,
+                    process_id,
+                    process_path,
+                    process_name,
+                    transient,
+                    window_title,
+                )
+            }
+        };
+        failed_polls = 0;
+        // Process ids are reused, so what is remembered per process is also
+        // keyed on when it started.
+        let process_key = (args.include_elevation || args.new_app_grace > 0)
+            .then(|| (process_id, process_started(process_id).unwrap_or(0)));
+
+        let redact_title = watcher.title_rules.redacts(&process_name);
+        let title = if redact_title {
+            process_name.clone()
+        } else {
+            rules::scrub_paths(&watcher.path_patterns, &window_title)
+        };
+        let significant_title = rules::significant_title(&watcher.title_ignore, &title);
+        let app = process_name;
+        let bucket = watcher.bucket_for(&app);
+        let stable_title = watcher.stable_title.iter().any(|r| r.is_match(&app));
+
+        #[cfg(feature = "uia")]
+        let tab = match &uia {
+            Some(uia) if args.terminal_tabs && !redact_title && app == "WindowsTerminal.exe" => {
+                match timed(&mut profiler, "SelectedTab", || match &active_window {
+                    Some(hwnd) => uia.selected_tab(hwnd),
+                    None => Ok(None),
+                }) {
+                    Ok(tab) => tab.map(|tab| {
+                        rules::scrub_paths(&watcher.path_patterns, &rules::sanitize_title(&tab))
+                    }),
+                    Err(e) => {
+                        error!("Failed to read the selected terminal tab: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        #[cfg(feature = "uia")]
+        let selection_len = match &uia {
+            Some(uia) if args.capture_selection => {
+                match timed(&mut profiler, "SelectionLength", || uia.selection_len()) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        debug!("Failed to read the text selection: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        #[cfg(not(feature = "uia"))]
+        let (tab, selection_len): (Option<String>, Option<usize>) = (None, None);
+
+        // Rules and buckets above keep matching the executable name.
+        let app = match (process_path.as_ref(), args.windows_app_source) {
+            (Some(path), AppSource::Description) => descriptions
+                .entry(path.clone())
+                .or_insert_with_key(|path| version::file_description(path))
+                .clone()
+                .unwrap_or(app),
+            // Every UWP app has the same host executable, so the id is
+            // looked up per process instead.
+            (Some(_), AppSource::Aumid) => {
+                let app_process = active_window
+                    .as_ref()
+                    .map_or(process_id, |hwnd| package::app_process(hwnd, process_id));
+                let key = (app_process, process_started(app_process).unwrap_or(0));
+                let app_id = match app_ids.get(&key) {
+                    Some(app_id) => app_id.clone(),
+                    None => {
+                        let app_id = package::app_user_model_id(app_process);
+                        app_ids.insert(key, app_id.clone(), process_running);
+                        app_id
+                    }
+                };
+                app_id.unwrap_or(app)
+            }
+            _ => app,
+        };
+        let (title, tab) = match (&args.hash_salt, args.hash_titles) {
+            (Some(salt), true) => (
+                anonymize::hash(salt, &title),
+                tab.map(|tab| anonymize::hash(salt, &tab)),
+            ),
+            _ => (title, tab),
+        };
+        let (app, title) = match (&args.hash_salt, args.hash_apps) {
+            (Some(salt), true) => {
+                let app_hash = anonymize::hash(salt, &app);
+                // A redacted title is the process name and would give the app
+                // away. With --hash-titles it already equals the app's hash.
+                let title = if redact_title && !args.hash_titles {
+                    app_hash.clone()
+                } else {
+                    title
+                };
+                (app_hash, title)
+            }
+            _ => (app, title),
+        };
+
+        // The path names the app, so it is hashed along with it.
+        let path = process_path.as_ref().map(|path| {
+            let path = path.to_string_lossy();
+            match (&args.hash_salt, args.hash_apps) {
+                (Some(salt), true) => anonymize::hash(salt, &path),
+                _ => path.into_owned(),
+            }
+        });
 
-    #[arg(long, default_value_t = false, help = "Disable title reporting")]
-    exclude_title: bool,
+        let mut data = Map::new();
+        data.insert("app".to_string(), Value::String(app));
+        data.insert("title".to_string(), Value::String(title));
+        if let Some(path) = path.as_ref().filter(|_| args.include_path) {
+            data.insert("path".to_string(), Value::String(path.clone()));
+        }
+        if redact_title && args.annotate_redaction {
+            data.insert("title_redacted".to_string(), Value::Bool(true));
+        }
+        if let Some(tab) = tab {
+            data.insert("tab".to_string(), Value::String(tab));
+        }
+        if let Some(desktop) = desktop {
+            data.insert("desktop".to_string(), Value::String(desktop));
+        }
+        if let Some(len) = selection_len {
+            data.insert("selection_len".to_string(), Value::from(len));
+        }
+        if args.include_user {
+            if let Some(user) = users.current() {
+                data.insert("user".to_string(), Value::String(user));
+            }
+        }
+        if let Some(path) = process_path.filter(|_| args.include_app_version) {
+            let app_version = app_versions
+                .entry(path)
+                .or_insert_with_key(|path| version::product_version(path));
+            if let Some(app_version) = app_version {
+                data.insert(
+                    "app_version".to_string(),
+                    Value::String(app_version.clone()),
+                );
+            }
+        }
+        if let Some(process_key) = process_key.filter(|_| args.include_elevation) {
+            let elevated = match elevations.get(&process_key) {
+                Some(elevated) => *elevated,
+                None => {
+                    let elevated = elevation::is_elevated(process_id);
+                    elevations.insert(process_key, elevated, process_running);
+                    elevated
+                }
+            };
+            if let Some(elevated) = elevated {
+                data.insert("elevated".to_string(), Value::Bool(elevated));
+            }
+        }
+        if let Some(hwnd) = active_window.as_ref().filter(|_| args.include_active_input) {
+            if let Some(active) = input::receives_input(hwnd) {
+                data.insert("active_input".to_string(), Value::Bool(active));
+            }
+        }
+        if let Some(hwnd) = active_window.as_ref().filter(|_| args.include_topmost) {
+            let ex_style = hwnd.GetWindowLongPtr(co::GWLP::EXSTYLE) as u32;
+            let topmost = ex_style & co::WS_EX::TOPMOST.raw() != 0;
+            data.insert("topmost".to_string(), Value::Bool(topmost));
+        }
+        if args.detect_recording {
+            match timed(&mut profiler, "DetectRecording", || {
+                recording::is_recording(&watcher.recorders)
+            }) {
+                Ok(true) => {
+                    data.insert("recording".to_string(), Value::Bool(true));
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to list processes: {}", e),
+            }
+        }
+        if args.include_timezone {
+            let offset = Local::now().offset().to_string();
+            data.insert("tz".to_string(), Value::String(offset));
+        }
+        if args.include_schema_version {
+            data.insert("schema".to_string(), Value::from(SCHEMA_VERSION));
+        }
 
-    #[arg(short, long, num_args = 1.., value_delimiter = ',', help = "Comma-separated list of regex patterns that matches process names (e.g., Firefox.exe) to exclude titles from")]
-    exclude_title_processes: Vec<String>,
+        // Parts of the title matched by --title-ignore-pattern do not split
+        // events. The event keeps the full title it was opened with, since a
+        // heartbeat with different data would start a new event on the server.
+        if let Some(fields) = &args.fields {
+            data.retain(|field, _| fields.contains(field));
+        }
+        let mut key = data.clone();
+        if let Some(path) = path.filter(|_| args.dedup_by == DedupBy::Path) {
+            key.insert("path".to_string(), Value::String(path));
+        }
+        if key.contains_key("title") {
+            key.insert("title".to_string(), Value::String(significant_title));
+        }
+        // Only switching to another app ends the event of an app with a
+        // stable title, and the event keeps the title it was opened with.
+        let key = rules::window_key(key, stable_title);
+        if let Some(template) = &args.label_template {
+            let label = template.render(&data);
+            if args.label_only {
+                data.remove("app");
+                data.remove("title");
+            }
+            if args.sends("label") {
+                data.insert("label".to_string(), Value::String(label));
+            }
+        }
+        // A process seen for the first time, usually a newly launched app, is
+        // only reported once its window has been stable for --new-app-grace,
+        // so splash screens do not get events of their own.
+        let unseen =
+            process_key.filter(|key| args.new_app_grace > 0 && seen_processes.get(key).is_none());
+        let settling = if let Some(process_key) = unseen {
+            let now = Utc::now();
+            match &new_app {
+                Some((pid, new_key, since)) if *pid == process_id && *new_key == key => {
+                    if now - *since < Duration::milliseconds(args.new_app_grace.into()) {
+                        true
+                    } else {
+                        seen_processes.insert(process_key, (), process_running);
+                        new_app = None;
+                        false
+                    }
+                }
+                _ => {
+                    new_app = Some((process_id, key.clone(), now));
+                    true
+                }
+            }
+        } else {
+            false
+        };
+        if settling && prev_data.is_empty() {
+            continue;
+        }
+        let (data, key, bucket) = if (transient || settling) && !prev_data.is_empty() {
+            debug!("Attributing a transient or new window to the current event");
+            (prev_data.clone(), prev_key.clone(), prev_bucket)
+        } else {
+            (data, key, bucket)
+        };
+        let ((data, bucket), key) = match &mut sampler {
+            Some(sampler) => match sampler.record((data, bucket), key) {
+                Some(sample) => sample,
+                None => continue,
+            },
+            None => ((data, bucket), key),
+        };
+        let ((data, bucket), key) = match &mut coalescer {
+            Some(coalescer) => match coalescer.record((data, bucket), rules::app_key(&key), key) {
+                Some(sample) => sample,
+                None => continue,
+            },
+            None => ((data, bucket), key),
+        };
+        let key = match args.title_similarity {
+            Some(threshold) if similar_title(&prev_key, &key, threshold) => prev_key.clone(),
+            _ => key,
+        };
+        if key == prev_key {
+            last_sent = round(Utc::now(), args).max(last_sent);
+            if let Some(threshold) = args.deep_focus_threshold {
+                let focused = last_sent - event_start;
+                if sampled
+                    && !deep_focus_marked
+                    && focused >= Duration::milliseconds(threshold.into())
+                {
+                    deep_focus_marked = true;
+                    mark_deep_focus(watcher, client, &prev_data, event_start, focused);
+                }
+            }
+            if !sampled || args.completed_events {
+                continue;
+            }
+            if args.change_only_heartbeats
+                && last_sent - last_beat < Duration::milliseconds(args.keepalive_interval.into())
+            {
+                continue;
+            }
+            last_beat = last_sent;
+            let event = new_event(prev_data.clone(), last_sent, Duration::zero(), bucket, args);
+            timed(&mut profiler, "heartbeat", || {
+                send_or_queue(&mut lock(retry_queue), event, client, bucket, watcher)
+            });
+            continue;
+        }
 
-    #[arg(short, long, num_args = 1.., value_delimiter = ',', help = "Override the exclusion rule for processes with regex patterns")]
-    include_title_processes: Vec<String>,
+        // Under clock jitter or very fast switches, now - 1ms can land at or
+        // before the previous heartbeat, which some servers reject.
+        let switch = switch_time(last_sent, args);
+        let closed_at = (switch - Duration::milliseconds(1)).max(fence(last_sent));
+        let now = switch.max(fence(closed_at));
+        if args.trace_splits && !prev_key.is_empty() {
+            trace_split(&prev_key, &key);
+        }
+        let closing_data = std::mem::replace(&mut prev_data, data.clone());
+        prev_key = key;
+        if args.emit_current {
+            let field = |name: &str| data.get(name).and_then(Value::as_str).unwrap_or_default();
+            println!("{} | {}", field("app"), field("title"));
+        }
 
-    #[arg(long, default_value_t = 5000, help = "Poll time in milliseconds")]
-    poll_time: u32,
+        // Both halves of a window change go through the retry queue, so a
+        // failure to open the new event never leaves a gap after the
+        // previous one has already been closed.
+        if !closing_data.is_empty() && sampled {
+            #[cfg(feature = "webhook")]
+            finish(watcher, &closing_data, event_start, closed_at, prev_bucket);
+            let closing = closing_event(
+                closing_data,
+                event_start,
+                last_sent,
+                closed_at,
+                prev_bucket,
+                args,
+            );
+            timed(&mut profiler, "heartbeat", || {
+                send_or_queue(
+                    &mut lock(retry_queue),
+                    closing,
+                    client,
+                    prev_bucket,
+                    watcher,
+                )
+            });
+        }
 
-    #[arg(long, default_value_t = false, help = "Enable debug logging")]
-    debug: bool,
+        // Whole events are kept or dropped, so the kept ones still start and
+        // end at the right time.
+        sampled = args.sample_rate >= 1.0 || rand::thread_rng().gen_bool(args.sample_rate);
+        if sampled {
+            let opening = new_event(data, now, Duration::zero(), bucket, args);
+            #[cfg(feature = "mqtt")]
+            if let Some(mqtt) = &watcher.mqtt {
+                mqtt.publish(&opening);
+            }
+            if !args.completed_events {
+                timed(&mut profiler, "heartbeat", || {
+                    send_or_queue(&mut lock(retry_queue), opening, client, bucket, watcher)
+                });
+            }
+        }
+        last_sent = now;
+        last_beat = now;
+        prev_bucket = bucket;
+        event_start = now;
+        deep_focus_marked = false;
+    }
 }
 
-fn main() {
-    let args = Args::parse();
-    let hostname = gethostname::gethostname()
-        .into_string()
-        .expect("Failed to get hostname");
-    let client =
-        aw_client_rust::blocking::AwClient::new(&args.host, args.port, "aw-watcher-window-rs")
-            .expect("Failed to create a client");
-    let exclude_title_processes = args
-        .exclude_title_processes
-        .iter()
-        .map(|s| Regex::new(s).unwrap_or_else(|_| Regex::new(regex::escape(s).as_str()).unwrap()))
-        .collect::<Vec<Regex>>();
-    let include_title_processes = args
-        .include_title_processes
-        .iter()
-        .map(|s| Regex::new(s).unwrap_or_else(|_| Regex::new(regex::escape(s).as_str()).unwrap()))
-        .collect::<Vec<Regex>>();
-    let window_bucket = format!("aw-watcher-window-rs_{}", hostname);
-
-    loop {
-        match client.create_bucket_simple(&window_bucket, "currentwindow") {
-            Ok(_) => break,
-            Err(e) => {
-                eprintln!("Failed to create bucket: {}. Retrying...", e);
-                thread::sleep(time::Duration::from_millis(1000));
+/// Returns the most recent event across the watcher's buckets if it ended
+/// recently enough for a heartbeat to still be merged into it.
+fn last_event<'a>(
+    watcher: &'a Watcher,
+    client: &aw_client_rust::blocking::AwClient,
+) -> Option<(&'a str, aw_client_rust::Event)> {
+    if watcher.args.dry_run {
+        return None;
+    }
+    let buckets = std::iter::once(&watcher.window_bucket)
+        .chain(watcher.routes.iter().map(|(_, bucket)| bucket));
+    let mut last: Option<(&str, aw_client_rust::Event)> = None;
+    for bucket in buckets {
+        match client.get_events(bucket, None, None, Some(1)) {
+            Ok(events) => {
+                if let Some(event) = events.into_iter().next() {
+                    let end = event.timestamp + event.duration;
+                    let older = last
+                        .as_ref()
+                        .is_some_and(|(_, last)| end <= last.timestamp + last.duration);
+                    if !older {
+                        last = Some((bucket, event));
+                    }
+                }
             }
+            Err(e) => warn!(
+                "Failed to read the last event of {}: {}",
+                bucket,
+                WatcherError::from(e)
+            ),
+        }
+    }
+    let pulsetime = Duration::milliseconds((watcher.args.pulsetime() * 1000.0) as i64);
+    last.filter(|(_, event)| event.timestamp + event.duration + pulsetime >= Utc::now())
+}
+
+/// Records in the deep focus bucket that the event started at `start` has
+/// been focused for `focused` without a break.
+fn mark_deep_focus(
+    watcher: &Watcher,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    data: &Map<String, Value>,
+    start: DateTime<Utc>,
+    focused: Duration,
+) {
+    let bucket = match &watcher.deep_focus_bucket {
+        Some(bucket) => bucket,
+        None => return,
+    };
+    debug!("Deep focus for {}s", focused.num_seconds());
+    if watcher.args.dry_run {
+        return;
+    }
+    let marker = new_event(data.clone(), start, focused, bucket, &watcher.args);
+    let bucket = bucket.clone();
+    if let Err(e) = request(client, &watcher.args, move |client| {
+        client.insert_event(&bucket, &marker)
+    }) {
+        warn!("Failed to record deep focus: {}", e);
+    }
+}
+
+/// Returns whether `key` differs from `prev_key` only in a title that is
+/// at least `threshold` similar to the previous one.
+fn similar_title(prev_key: &Map<String, Value>, key: &Map<String, Value>, threshold: f64) -> bool {
+    let (Some(Value::String(prev_title)), Some(Value::String(title))) =
+        (prev_key.get("title"), key.get("title"))
+    else {
+        return false;
+    };
+    prev_title != title
+        && prev_key.len() == key.len()
+        && key
+            .iter()
+            .all(|(field, value)| field == "title" || prev_key.get(field) == Some(value))
+        && rules::title_similarity(prev_title, title) >= threshold
+}
+
+/// Logs every field that differs between the change detection keys of the
+/// event that ends and the one that starts.
+fn trace_split(prev_key: &Map<String, Value>, key: &Map<String, Value>) {
+    let fields = prev_key.keys().chain(key.keys()).collect::<BTreeSet<_>>();
+    for field in fields {
+        let (old, new) = (prev_key.get(field), key.get(field));
+        if old != new {
+            let show = |value: Option<&Value>| value.map_or("(none)".to_string(), Value::to_string);
+            info!(
+                "Event split: {} changed from {} to {}",
+                field,
+                show(old),
+                show(new)
+            );
         }
     }
+}
 
-    let mut prev_app = String::new();
-    let mut prev_title = String::new();
+/// Rebuilds the change detection key of event data sent earlier.
+fn change_key(watcher: &Watcher, data: &Map<String, Value>) -> Map<String, Value> {
+    let mut key = data.clone();
+    key.remove("label");
+    if let Some(Value::String(title)) = key.get("title") {
+        let title = rules::significant_title(&watcher.title_ignore, title);
+        key.insert("title".to_string(), Value::String(title));
+    }
+    let stable_title = match key.get("app") {
+        Some(Value::String(app)) => watcher.stable_title.iter().any(|r| r.is_match(app)),
+        _ => false,
+    };
+    rules::window_key(
+        key,
+        watcher.args.coalesce_app_window.is_some() || stable_title,
+    )
+}
 
-    loop {
-        thread::sleep(time::Duration::from_millis(args.poll_time.into()));
-        let active_window = match HWND::GetForegroundWindow() {
-            Some(hwnd) => hwnd,
-            None => {
-                if args.debug {
-                    println!("No active window found");
-                }
-                thread::sleep(time::Duration::from_millis(args.poll_time.into()));
-                continue;
+/// Sends the event that ended at `end` to the webhook, if there is one.
+#[cfg(feature = "webhook")]
+fn finish(
+    watcher: &Watcher,
+    data: &Map<String, Value>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: &str,
+) {
+    if let Some(webhook) = &watcher.webhook {
+        webhook.send(&new_event(
+            data.clone(),
+            start,
+            end - start,
+            bucket,
+            &watcher.args,
+        ));
+    }
+}
+
+/// Sends a heartbeat with the topmost window of each monitor to that
+/// monitor's bucket, creating the bucket the first time the monitor is seen.
+/// Title rules and hashing apply as they do to the foreground window.
+fn report_monitors(
+    watcher: &Watcher,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    created: &mut HashSet<String>,
+    profiler: &mut Option<Profiler>,
+) {
+    let args = &watcher.args;
+    let windows = match timed(profiler, "TopmostWindows", monitors::topmost_windows) {
+        Ok(windows) => windows,
+        Err(e) => {
+            error!("Failed to enumerate windows: {}", e);
+            return;
+        }
+    };
+    for (device, hwnd) in windows {
+        let (_, process_id) = hwnd.GetWindowThreadProcessId();
+        let process_path = if watcher.capabilities.process_query {
+            query_process_path(profiler, process_id)
+        } else {
+            None
+        };
+        let process_name = match process_path.as_deref().and_then(executable_name) {
+            Some(name) => name,
+            None => format!("class:{}", hwnd.GetClassName().unwrap_or_default()),
+        };
+        let redact_title = watcher.title_rules.redacts(&process_name);
+        let title = if redact_title {
+            process_name.clone()
+        } else {
+            let title = rules::sanitize_title(&hwnd.GetWindowText().unwrap_or_default());
+            rules::scrub_paths(&watcher.path_patterns, &title)
+        };
+        let (app, title) = match &args.hash_salt {
+            Some(salt) => {
+                let app = if args.hash_apps {
+                    anonymize::hash(salt, &process_name)
+                } else {
+                    process_name
+                };
+                let title = if args.hash_titles {
+                    anonymize::hash(salt, &title)
+                } else if redact_title && args.hash_apps {
+                    app.clone()
+                } else {
+                    title
+                };
+                (app, title)
             }
+            None => (process_name, title),
         };
-        let (_, process_id) = active_window.GetWindowThreadProcessId();
 
-        let process_handle =
-            match HPROCESS::OpenProcess(co::PROCESS::QUERY_INFORMATION, false, process_id) {
-                Ok(handle) => handle,
-                Err(e) => {
-                    eprintln!("Failed to open process handle: {}", e);
-                    continue;
-                }
-            };
+        let mut data = Map::new();
+        data.insert("app".to_string(), Value::String(app));
+        data.insert("title".to_string(), Value::String(title));
+        data.insert("monitor".to_string(), Value::String(device.clone()));
+        if let Some(fields) = &args.fields {
+            data.retain(|field, _| fields.contains(field));
+        }
+        debug!("Topmost window on {}: {}", device, log::Json(&data));
+        if args.dry_run {
+            continue;
+        }
 
-        let process_fullpath =
-            match process_handle.QueryFullProcessImageName(co::PROCESS_NAME::WIN32) {
-                Ok(path) => path,
+        let bucket = format!("{}-monitor-{}", watcher.window_bucket, device);
+        if !created.contains(&bucket) {
+            let new_bucket = bucket.clone();
+            match request(client, args, move |client| {
+                client.create_bucket_simple(&new_bucket, MONITOR_BUCKET_TYPE)
+            }) {
+                Ok(_) => {}
+                Err(e) if e.is_already_exists() => {}
                 Err(e) => {
-                    eprintln!("Failed to query process path: {}", e);
+                    warn!("Failed to create bucket {}: {}", bucket, e);
                     continue;
                 }
-            };
-
-        let pathbuf = PathBuf::from(process_fullpath);
-        let process_name = match pathbuf.file_name().unwrap().to_str() {
-            Some(s) => s,
-            None => {
-                eprintln!("Failed to convert process name to string");
-                continue;
             }
-        };
+            created.insert(bucket.clone());
+        }
+        let event = new_event(data, Utc::now(), Duration::zero(), &bucket, args);
+        let pulsetime = args.pulsetime();
+        if let Err(e) = request(client, args, move |client| {
+            client.heartbeat(&bucket, &event, pulsetime)
+        }) {
+            warn!("Failed to send heartbeat for {}: {}", device, e);
+        }
+    }
+}
 
-        let window_title = match active_window.GetWindowText() {
-            Ok(title) => title,
-            Err(e) => {
-                eprintln!("Failed to get window title: {}", e);
-                continue;
+/// Extends the watcher's event in the liveness bucket. Being sent from the
+/// poll loop, it also stops when polling is stuck, not only when the process
+/// has exited.
+fn report_alive(client: &Arc<aw_client_rust::blocking::AwClient>, bucket: &str, args: &Args) {
+    if args.dry_run {
+        return;
+    }
+    let mut data = Map::new();
+    data.insert("status".to_string(), Value::String("alive".to_string()));
+    let event = new_event(data, Utc::now(), Duration::zero(), bucket, args);
+    // One missed interval ends the event.
+    let pulsetime = LIVENESS_INTERVAL.as_secs_f64() * 1.5;
+    let bucket = bucket.to_string();
+    if let Err(e) = request(client, args, move |client| {
+        client.heartbeat(&bucket, &event, pulsetime)
+    }) {
+        warn!("Failed to send liveness heartbeat: {}", e);
+    }
+}
+
+/// Sends the AFK status heartbeats for this poll, with the same pulsetime
+/// as aw-watcher-afk.
+fn report_afk(
+    afk: &mut Afk,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    bucket: &str,
+    args: &Args,
+) {
+    let Some(idle) = session::idle_time() else {
+        debug!("Failed to read the time of the last input");
+        return;
+    };
+    let idle = Duration::from_std(idle).unwrap_or(Duration::zero());
+    for heartbeat in afk.poll(Utc::now(), idle) {
+        let status = if heartbeat.afk { "afk" } else { "not-afk" };
+        let mut data = Map::new();
+        data.insert("status".to_string(), Value::String(status.to_string()));
+        let event = new_event(data, heartbeat.timestamp, heartbeat.duration, bucket, args);
+        debug!("Logging AFK status: {}", status);
+        if args.dry_run {
+            continue;
+        }
+        let pulsetime = f64::from(args.poll_time.saturating_add(1000)) / 1000.0;
+        let bucket = bucket.to_string();
+        if let Err(e) = request(client, args, move |client| {
+            client.heartbeat(&bucket, &event, pulsetime)
+        }) {
+            warn!("Failed to send AFK heartbeat: {}", e);
+        }
+    }
+}
+
+/// Walks from a focused dialog or untitled popup to the window that owns
+/// it, which carries the title of what is being worked on. The PID is then
+/// taken from the owner as well.
+fn resolve_owner(mut hwnd: HWND) -> HWND {
+    loop {
+        let untitled = hwnd
+            .GetWindowText()
+            .map_or(true, |title| title.trim().is_empty());
+        let dialog = hwnd.GetClassName().is_ok_and(|class| class == "#32770");
+        if !(untitled || dialog) {
+            return hwnd;
+        }
+        match hwnd.GetWindow(co::GW::OWNER) {
+            Ok(owner) => hwnd = owner,
+            Err(_) => return hwnd,
+        }
+    }
+}
+
+/// Returns whether `hwnd` is the taskbar, the Start menu or search, which
+/// take focus only for the moment it takes to launch or switch to something.
+fn is_shell_window(hwnd: &HWND, process_name: &str) -> bool {
+    const SHELL_PROCESSES: &[&str] = &[
+        "StartMenuExperienceHost.exe",
+        "SearchHost.exe",
+        "SearchApp.exe",
+        "ShellExperienceHost.exe",
+    ];
+    const TASKBAR_CLASSES: &[&str] = &["Shell_TrayWnd", "Shell_SecondaryTrayWnd"];
+
+    if SHELL_PROCESSES.contains(&process_name) {
+        return true;
+    }
+    process_name.eq_ignore_ascii_case("explorer.exe")
+        && hwnd
+            .GetClassName()
+            .is_ok_and(|class| TASKBAR_CLASSES.contains(&class.as_str()))
+}
+
+/// Returns the path of the process's executable, logging why if it cannot
+/// be read.
+fn query_process_path(profiler: &mut Option<Profiler>, process_id: u32) -> Option<PathBuf> {
+    let process_handle = match timed(profiler, "OpenProcess", || {
+        HPROCESS::OpenProcess(co::PROCESS::QUERY_INFORMATION, false, process_id)
+    }) {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("Failed to open process handle: {}", e);
+            return None;
+        }
+    };
+
+    let process_fullpath = match timed(profiler, "QueryFullProcessImageName", || {
+        process_handle.QueryFullProcessImageName(co::PROCESS_NAME::WIN32)
+    }) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Failed to query process path: {}", e);
+            return None;
+        }
+    };
+
+    Some(PathBuf::from(process_fullpath))
+}
+
+/// Returns when a process started, in FILETIME ticks, or None if it cannot
+/// be opened.
+fn process_started(process_id: u32) -> Option<u64> {
+    let process =
+        HPROCESS::OpenProcess(co::PROCESS::QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+    let (created, _, _, _) = process.GetProcessTimes().ok()?;
+    Some(((created.dwHighDateTime as u64) << 32) | created.dwLowDateTime as u64)
+}
+
+/// Returns whether the process `key` identifies is still running, rather
+/// than having exited, possibly leaving its id to another process.
+fn process_running(&(process_id, started): &ProcessKey) -> bool {
+    process_started(process_id) == Some(started)
+}
+
+/// Returns the file name of an executable, logging why if it is not valid
+/// Unicode.
+fn executable_name(path: &Path) -> Option<String> {
+    match path.file_name().unwrap().to_str() {
+        Some(s) => Some(s.to_string()),
+        None => {
+            error!("Failed to convert process name to string");
+            None
+        }
+    }
+}
+
+/// Waits until the next poll is due. With --event-driven, a foreground
+/// change makes it due right away.
+fn pause(args: &Args) {
+    let poll_time = time::Duration::from_millis(args.poll_time.into());
+    if args.event_driven {
+        foreground::wait(poll_time);
+    } else {
+        thread::sleep(poll_time);
+    }
+}
+
+/// Returns when the current window change happened. With
+/// --flush-on-focus-loss this is the time the focus hook saw the switch,
+/// as long as it leaves room to close the previous event after `last_sent`.
+fn switch_time(last_sent: DateTime<Utc>, args: &Args) -> DateTime<Utc> {
+    if args.flush_on_focus_loss {
+        if let Some(switch) = foreground::last_switch_after(fence(last_sent)) {
+            return round(switch, args);
+        }
+    }
+    round(Utc::now(), args)
+}
+
+/// Rounds `time` down to a multiple of --round-timestamps. Callers clamp
+/// the result against the previous heartbeat with `fence`, since rounding
+/// can move two timestamps onto the same point.
+fn round(time: DateTime<Utc>, args: &Args) -> DateTime<Utc> {
+    match args.round_timestamps {
+        Some(step) => {
+            let millis = time.timestamp_millis();
+            let rounded = millis - millis.rem_euclid(step.into());
+            DateTime::from_timestamp_millis(rounded).unwrap_or(time)
+        }
+        None => time,
+    }
+}
+
+/// Reads the title of `hwnd` from each of `sources` in turn and returns the
+/// first one that is not empty. Fails only if GetWindowText failed and no
+/// later source had a title either.
+fn read_title(
+    hwnd: &HWND,
+    sources: &[TitleSource],
+    #[cfg(feature = "uia")] uia: Option<&Uia>,
+    profiler: &mut Option<Profiler>,
+) -> winsafe::SysResult<String> {
+    let mut error = None;
+    for source in sources {
+        let title = match source {
+            TitleSource::GetWindowText => {
+                match timed(profiler, "GetWindowText", || hwnd.GetWindowText()) {
+                    Ok(title) => Some(title),
+                    Err(e) => {
+                        error = Some(e);
+                        None
+                    }
+                }
             }
+            TitleSource::WmGettext => timed(profiler, "WM_GETTEXT", || window_text::get_text(hwnd)),
+            #[cfg(feature = "uia")]
+            TitleSource::Uia | TitleSource::Msaa => match uia {
+                Some(uia) if window_text::responds(hwnd) => {
+                    let name = if *source == TitleSource::Uia {
+                        timed(profiler, "UIA Name", || uia.window_name(hwnd))
+                    } else {
+                        timed(profiler, "MSAA Name", || uia.msaa_name(hwnd))
+                    };
+                    name.map_err(|e| debug!("Failed to read the accessible name: {}", e))
+                        .ok()
+                }
+                _ => None,
+            },
+            #[cfg(not(feature = "uia"))]
+            TitleSource::Uia | TitleSource::Msaa => None,
         };
+        if let Some(title) = title.filter(|title| !title.is_empty()) {
+            return Ok(rules::sanitize_title(&title));
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(String::new()),
+    }
+}
 
-        let app = process_name.to_string();
-        let title = if (args.exclude_title
-            || exclude_title_processes
-                .iter()
-                .any(|r| r.is_match(&process_name.to_string())))
-            && !include_title_processes
-                .iter()
-                .any(|r| r.is_match(&process_name.to_string()))
-        {
-            process_name.to_string()
+/// Returns the earliest timestamp a heartbeat following one at `last_sent`
+/// may have.
+fn fence(last_sent: DateTime<Utc>) -> DateTime<Utc> {
+    last_sent + Duration::milliseconds(1)
+}
+
+/// Prints what the watcher can see of every visible top-level window, to
+/// help with writing title rules.
+fn list_windows() {
+    println!("hwnd\tpid\tprocess\tclass\ttitle");
+    let result = winsafe::EnumWindows(|hwnd: HWND| {
+        if !hwnd.IsWindowVisible() {
+            return true;
+        }
+        let (_, process_id) = hwnd.GetWindowThreadProcessId();
+        let process_name =
+            query_process_path(&mut None, process_id).and_then(|path| executable_name(&path));
+        println!(
+            "{:#x}\t{}\t{}\t{}\t{}",
+            hwnd.ptr() as usize,
+            process_id,
+            process_name.as_deref().unwrap_or("?"),
+            hwnd.GetClassName().unwrap_or_default(),
+            hwnd.GetWindowText().unwrap_or_default()
+        );
+        true
+    });
+    if let Err(e) = result {
+        error!("Failed to enumerate windows: {}", e);
+    }
+}
+
+fn print_version() {
+    let features = env!("BUILD_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect::<Vec<&str>>();
+    let info = serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_hash": env!("GIT_HASH"),
+        "target": env!("BUILD_TARGET"),
+        "features": features,
+    });
+    println!("{}", info);
+}
+
+/// Parses the command line together with the config file.
+fn parse_args() -> ArgMatches {
+    let matches = Args::command().get_matches();
+    let Some(path) = config_path(&matches) else {
+        return matches;
+    };
+    let command = Args::command();
+    let config_args = match config::read_args(&path, &command, &matches) {
+        Ok(args) => args,
+        Err(e) => command.clone().error(ErrorKind::InvalidValue, e).exit(),
+    };
+    command.get_matches_from(with_config_args(config_args))
+}
+
+/// Parses the command line together with the config file again after the
+/// file changed, failing instead of exiting if it is no longer valid.
+fn reparse_args() -> Result<Args, String> {
+    let command = Args::command();
+    let matches = command
+        .clone()
+        .try_get_matches()
+        .map_err(|e| e.to_string())?;
+    let Some(path) = config_path(&matches) else {
+        return Err("the config file was removed".to_string());
+    };
+    let config_args = config::read_args(&path, &command, &matches)?;
+    command
+        .try_get_matches_from(with_config_args(config_args))
+        .and_then(|matches| Args::from_arg_matches(&matches))
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the config file to read, which is the one given with --config,
+/// or the default one if it exists.
+fn config_path(matches: &ArgMatches) -> Option<PathBuf> {
+    match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => config::default_path().filter(|path| path.exists()),
+    }
+}
+
+/// Returns the command line with `config_args` inserted before the options
+/// given on it, and before any subcommand.
+fn with_config_args(config_args: Vec<OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    args.splice(1..1, config_args);
+    args
+}
+
+/// Prints the value every option has after defaults are applied, keyed by
+/// its long flag, with durations in milliseconds and secrets replaced.
+fn print_config(args: &Args, matches: &ArgMatches) {
+    let mut config = Map::new();
+    for arg in Args::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(name) = arg.get_long().filter(|_| id != "print_config") else {
+            continue;
+        };
+        let value = match id {
+            // Their defaults depend on other options.
+            "port" => Value::from(args.port()),
+            "pulsetime" => Value::from((args.pulsetime() * 1000.0).round() as u64),
+            "request_timeout" => Value::from(args.request_timeout()),
+            _ => parsed_value(matches, arg),
+        };
+        let value = if SECRET_OPTIONS.contains(&id) && !value.is_null() {
+            Value::String("<redacted>".to_string())
         } else {
-            window_title
+            value
         };
+        config.insert(name.to_string(), value);
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&Value::Object(config)).unwrap()
+    );
+}
 
-        if app == prev_app && title == prev_title {
-            let mut data = Map::new();
-            data.insert("app".to_string(), Value::String(app.clone()));
-            data.insert("title".to_string(), Value::String(title.clone()));
-            match ping(data, &client, &window_bucket, Utc::now(), &args) {
-                Ok(_) => (),
-                Err(e) => eprintln!("Failed to send heartbeat: {}", e),
-            }
-            continue;
+/// Returns the value `matches` has for `arg` as parsed, so numbers and
+/// durations come out as numbers. Other values are given as they were
+/// passed.
+fn parsed_value(matches: &ArgMatches, arg: &clap::Arg) -> Value {
+    fn typed<T: Clone + Into<Value> + Send + Sync + 'static>(
+        matches: &ArgMatches,
+        id: &str,
+    ) -> Option<Vec<Value>> {
+        match matches.try_get_many::<T>(id) {
+            Ok(values) => Some(
+                values
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .map(Into::into)
+                    .collect(),
+            ),
+            Err(_) => None,
+        }
+    }
+    let id = arg.get_id().as_str();
+    let values = typed::<bool>(matches, id)
+        .or_else(|| typed::<u16>(matches, id))
+        .or_else(|| typed::<u32>(matches, id))
+        .or_else(|| typed::<u64>(matches, id))
+        .or_else(|| typed::<usize>(matches, id))
+        .or_else(|| typed::<f64>(matches, id))
+        .unwrap_or_else(|| match matches.try_get_raw(id) {
+            Ok(Some(values)) => values
+                .map(|value| Value::String(value.to_string_lossy().into_owned()))
+                .collect(),
+            _ => Vec::new(),
+        });
+    match (arg.get_action(), values.len()) {
+        (ArgAction::Append, _) => Value::Array(values),
+        (_, 0) => Value::Null,
+        (_, 1) => values.into_iter().next().unwrap(),
+        _ => Value::Array(values),
+    }
+}
+
+/// Reports every pattern that is not a valid regex and would be matched
+/// literally, and exits with an error if there is one. Everything else is
+/// checked by the time this runs.
+fn validate(args: &Args) {
+    let patterns = [
+        ("--redact-title-for", &args.redact_title_for),
+        ("--keep-title-for", &args.keep_title_for),
+        ("--title-ignore-pattern", &args.title_ignore_pattern),
+        ("--recorder-processes", &args.recorder_processes),
+        ("--sticky-process", &args.sticky_process),
+        ("--stable-title-for", &args.stable_title_for),
+    ];
+    let routes: Vec<String> = args
+        .route
+        .iter()
+        .map(|(pattern, _)| pattern.clone())
+        .collect();
+    let mut invalid = 0;
+    for (flag, patterns) in patterns.into_iter().chain([("--route", &routes)]) {
+        for pattern in patterns.iter().filter(|p| rules::is_literal(p)) {
+            error!(
+                "{} '{}' is not a valid regex and would be matched literally",
+                flag, pattern
+            );
+            invalid += 1;
         }
+    }
+    if invalid > 0 {
+        std::process::exit(1);
+    }
+    info!("The arguments are valid");
+}
 
-        let mut prev_data = Map::new();
-        prev_data.insert("app".to_string(), Value::String(prev_app.clone()));
-        prev_data.insert("title".to_string(), Value::String(prev_title.clone()));
+/// Builds the heartbeat for `data` at `timestamp`.
+fn new_event(
+    data: Map<String, Value>,
+    timestamp: DateTime<Utc>,
+    duration: Duration,
+    bucket: &str,
+    args: &Args,
+) -> aw_client_rust::Event {
+    let id = if args.emit_event_id {
+        Some(event_id(bucket, timestamp, &data))
+    } else {
+        None
+    };
+    aw_client_rust::Event {
+        id,
+        timestamp,
+        duration,
+        data,
+    }
+}
 
-        let now = Utc::now();
+/// Builds the heartbeat that closes the previous event at `closed_at`.
+/// With --explicit-durations it covers the time since the last heartbeat
+/// instead of being a zero-length point. With --completed-events it is the
+/// whole event since `event_start`.
+fn closing_event(
+    data: Map<String, Value>,
+    event_start: DateTime<Utc>,
+    last_sent: DateTime<Utc>,
+    closed_at: DateTime<Utc>,
+    bucket: &str,
+    args: &Args,
+) -> aw_client_rust::Event {
+    if args.completed_events {
+        new_event(data, event_start, closed_at - event_start, bucket, args)
+    } else if args.explicit_durations && last_sent < closed_at {
+        new_event(data, last_sent, closed_at - last_sent, bucket, args)
+    } else {
+        new_event(data, closed_at, Duration::zero(), bucket, args)
+    }
+}
 
-        match ping(
-            prev_data,
-            &client,
-            &window_bucket,
-            now - Duration::milliseconds(1),
-            &args,
-        ) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Failed to send heartbeat: {}", e);
-                continue;
+/// Sends a heartbeat, or queues it behind earlier failed ones so that
+/// heartbeats always reach the server in the order they were produced.
+fn send_or_queue(
+    queue: &mut Buffer<(String, aw_client_rust::Event)>,
+    event: aw_client_rust::Event,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    bucket: &str,
+    watcher: &Watcher,
+) {
+    let args = &watcher.args;
+    #[cfg(feature = "sqlite")]
+    if let Some(store) = &watcher.sqlite {
+        let result = if args.completed_events {
+            store.insert_event(bucket, &event)
+        } else {
+            store.heartbeat(bucket, &event, args.pulsetime())
+        };
+        if let Err(e) = result {
+            warn!("Failed to write event to SQLite: {}", e);
+        }
+    }
+    if queue.is_empty() {
+        match ping(&event, client, bucket, args) {
+            Ok(_) => return,
+            Err(e @ WatcherError::TimedOut(_)) => {
+                warn!(
+                    "{}. The heartbeat may still reach it, so it is not resent",
+                    e
+                );
+                return;
             }
+            Err(e) => warn!("Failed to send heartbeat: {}. Queued for retry", e),
+        }
+    }
+    if queue.is_full() && args.buffer_overflow == Overflow::Block {
+        warn!("Retry buffer is full. Pausing until the server accepts heartbeats again");
+        while queue.is_full() {
+            thread::sleep(jittered(
+                time::Duration::from_millis(args.poll_time.into()),
+                args.retry_jitter,
+            ));
+            flush_retry_queue(queue, client, args);
         }
+    }
+    queue.push((bucket.to_string(), event));
+}
+
+/// Locks the retry queue. A worker that panicked while holding it left the
+/// queue itself intact, so the poisoning is ignored.
+fn lock(queue: &RetryQueue) -> MutexGuard<'_, Buffer<(String, aw_client_rust::Event)>> {
+    queue.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
-        let mut new_data = Map::new();
-        new_data.insert("app".to_string(), Value::String(app.clone()));
-        new_data.insert("title".to_string(), Value::String(title.clone()));
-        match ping(new_data, &client, &window_bucket, now, &args) {
-            Ok(_) => (),
+fn flush_retry_queue(
+    queue: &mut Buffer<(String, aw_client_rust::Event)>,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
+    args: &Args,
+) {
+    while let Some((bucket, event)) = queue.front() {
+        match ping(event, client, bucket, args) {
+            Ok(_) => {
+                queue.pop_front();
+            }
+            Err(e @ WatcherError::TimedOut(_)) => {
+                warn!(
+                    "{}. The heartbeat may still reach it, so it is not resent",
+                    e
+                );
+                queue.pop_front();
+                break;
+            }
             Err(e) => {
-                eprintln!("Failed to send heartbeat: {}", e);
-                continue;
+                warn!(
+                    "Failed to resend heartbeat: {}. {} heartbeat(s) still queued",
+                    e,
+                    queue.len()
+                );
+                break;
             }
         }
-
-        prev_app = app;
-        prev_title = title;
     }
 }
 
 fn ping(
-    data: Map<String, Value>,
-    client: &aw_client_rust::blocking::AwClient,
+    event: &aw_client_rust::Event,
+    client: &Arc<aw_client_rust::blocking::AwClient>,
     bucket: &str,
-    timestamp: chrono::DateTime<Utc>,
     args: &Args,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if args.debug {
-        println!("Logging event: {:?}", data);
+) -> Result<(), WatcherError> {
+    debug!("Logging event: {}", log::Json(&event.data));
+    if args.dry_run {
+        return Ok(());
+    }
+    let (bucket, event) = (bucket.to_string(), event.clone());
+    if args.completed_events {
+        request(client, args, move |client| {
+            client.insert_event(&bucket, &event)
+        })
+    } else {
+        let pulsetime = args.pulsetime();
+        request(client, args, move |client| {
+            client.heartbeat(&bucket, &event, pulsetime)
+        })
     }
-    let event = aw_client_rust::Event {
-        id: None,
-        timestamp,
-        duration: Duration::seconds(0),
-        data,
-    };
-    client.heartbeat(bucket, &event, (args.poll_time + 1000) as f64)?;
-    Ok(())
 }
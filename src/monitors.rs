@@ -0,0 +1,56 @@
+use std::mem::size_of;
+use windows::Win32::Foundation::HWND as RawHwnd;
+use windows::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONULL,
+};
+use winsafe::{prelude::*, SysResult, HWND};
+
+/// Returns the topmost titled window on each monitor with the monitor's
+/// device name, such as DISPLAY1.
+///
+/// This walks every top-level window from the top of the z-order down, so
+/// it costs more than looking at the foreground window alone.
+pub fn topmost_windows() -> SysResult<Vec<(String, HWND)>> {
+    let mut topmost: Vec<(String, HWND)> = Vec::new();
+    winsafe::EnumWindows(|hwnd: HWND| {
+        if !hwnd.IsWindowVisible()
+            || hwnd.IsIconic()
+            || hwnd.GetWindowText().map_or(true, |title| title.is_empty())
+        {
+            return true;
+        }
+        if let Some(device) = monitor_name(&hwnd) {
+            if !topmost.iter().any(|(d, _)| *d == device) {
+                topmost.push((device, hwnd));
+            }
+        }
+        true
+    })?;
+    Ok(topmost)
+}
+
+fn monitor_name(hwnd: &HWND) -> Option<String> {
+    unsafe {
+        let monitor = MonitorFromWindow(RawHwnd(hwnd.ptr()), MONITOR_DEFAULTTONULL);
+        if monitor.is_invalid() {
+            return None;
+        }
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+        if !GetMonitorInfoW(
+            monitor,
+            &mut info as *mut MONITORINFOEXW as *mut MONITORINFO,
+        )
+        .as_bool()
+        {
+            return None;
+        }
+        let len = info
+            .szDevice
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(info.szDevice.len());
+        let device = String::from_utf16_lossy(&info.szDevice[..len]);
+        Some(device.trim_start_matches(r"\\.\").to_string())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::log::warn;
+use rumqttc::{Client, MqttOptions, QoS};
+use std::thread;
+use std::time::Duration;
+
+/// Publishes window events to an MQTT topic.
+pub struct Publisher {
+    client: Client,
+    topic: String,
+}
+
+impl Publisher {
+    /// Connects to `broker`, given as `host` or `host:port`, and starts a
+    /// thread that keeps the connection alive and reconnects on errors.
+    pub fn connect(broker: &str, topic: &str) -> Self {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => {
+                    warn!("Invalid MQTT port '{}'. Using 1883", port);
+                    (host, 1883)
+                }
+            },
+            None => (broker, 1883),
+        };
+        let mut options = MqttOptions::new(
+            format!("aw-watcher-window-rs-{}", std::process::id()),
+            host,
+            port,
+        );
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 16);
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                if let Err(e) = notification {
+                    warn!("MQTT connection error: {}. Reconnecting...", e);
+                    thread::sleep(Duration::from_secs(5));
+                }
+            }
+        });
+        Publisher {
+            client,
+            topic: topic.to_string(),
+        }
+    }
+
+    /// Queues `event` for publishing without blocking the poll loop.
+    pub fn publish(&self, event: &aw_client_rust::Event) {
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Failed to serialize event for MQTT: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self
+            .client
+            .try_publish(&self.topic, QoS::AtLeastOnce, false, payload)
+        {
+            warn!("Failed to publish event to MQTT: {}", e);
+        }
+    }
+}
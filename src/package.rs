@@ -0,0 +1,56 @@
+use windows::core::{w, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS, HWND as RawHwnd,
+};
+use windows::Win32::Storage::Packaging::Appx::GetApplicationUserModelId;
+use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+use windows::Win32::UI::WindowsAndMessaging::{FindWindowExW, GetWindowThreadProcessId};
+use winsafe::{prelude::*, HWND};
+
+/// Returns the process of the app that `hwnd`, owned by `process_id`,
+/// shows. UWP apps are shown in a frame window of ApplicationFrameHost.exe
+/// with the app's own CoreWindow as a child, so that child's process is
+/// the app's. Other windows belong to their own process.
+pub fn app_process(hwnd: &HWND, process_id: u32) -> u32 {
+    unsafe {
+        let core_window = FindWindowExW(
+            RawHwnd(hwnd.ptr()),
+            RawHwnd::default(),
+            w!("Windows.UI.Core.CoreWindow"),
+            PCWSTR::null(),
+        );
+        let mut app_id = 0;
+        if let Ok(core_window) = core_window {
+            GetWindowThreadProcessId(core_window, Some(&mut app_id));
+        }
+        if app_id == 0 {
+            process_id
+        } else {
+            app_id
+        }
+    }
+}
+
+/// Returns the AppUserModelID of a packaged app's process, such as
+/// `Microsoft.WindowsCalculator_8wekyb3d8bbwe!App`, or None for processes
+/// that do not belong to a package.
+pub fn app_user_model_id(process_id: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+        let mut len = 0u32;
+        let mut result = GetApplicationUserModelId(process, &mut len, PWSTR::null());
+        let mut buffer = Vec::new();
+        if result == ERROR_INSUFFICIENT_BUFFER {
+            buffer = vec![0u16; len as usize];
+            result = GetApplicationUserModelId(process, &mut len, PWSTR(buffer.as_mut_ptr()));
+        }
+        let _ = CloseHandle(process);
+        if result != ERROR_SUCCESS {
+            return None;
+        }
+        // The length includes the terminating null.
+        Some(String::from_utf16_lossy(
+            &buffer[..(len as usize).saturating_sub(1)],
+        ))
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+/// What owns the foreground window, as far as reporting it is concerned.
+#[derive(Debug, PartialEq)]
+pub enum Owner {
+    /// No process. The window is being destroyed, or was never owned by a
+    /// process, and opening process 0 would only fail.
+    Nobody,
+    /// The watcher itself.
+    Watcher,
+    /// Any other process.
+    Process(u32),
+}
+
+/// Classifies the process id GetWindowThreadProcessId returned for a window,
+/// given the watcher's own process id.
+pub fn owner(process_id: u32, own_id: u32) -> Owner {
+    match process_id {
+        0 => Owner::Nobody,
+        id if id == own_id => Owner::Watcher,
+        id => Owner::Process(id),
+    }
+}
+
+/// A process, told apart from later ones that reuse its id by when it
+/// started, in FILETIME ticks.
+pub type ProcessKey = (u32, u64);
+
+/// Fewest entries a `PerProcess` holds before it looks for exited processes.
+const MIN_PRUNE_AT: usize = 64;
+
+/// Values remembered per process. Entries of processes that have exited are
+/// dropped whenever the map has doubled in size since the last time, so it
+/// does not grow for as long as the watcher runs.
+pub struct PerProcess<V> {
+    values: HashMap<ProcessKey, V>,
+    prune_at: usize,
+}
+
+impl<V> Default for PerProcess<V> {
+    fn default() -> Self {
+        PerProcess {
+            values: HashMap::new(),
+            prune_at: MIN_PRUNE_AT,
+        }
+    }
+}
+
+impl<V> PerProcess<V> {
+    pub fn get(&self, key: &ProcessKey) -> Option<&V> {
+        self.values.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Remembers `value` for `key`. `running` tells whether a process is
+    /// still running, for dropping the entries of those that are not.
+    pub fn insert(&mut self, key: ProcessKey, value: V, running: impl Fn(&ProcessKey) -> bool) {
+        if self.values.len() >= self.prune_at {
+            self.values.retain(|key, _| running(key));
+            self.prune_at = (self.values.len() * 2).max(MIN_PRUNE_AT);
+        }
+        self.values.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_id_0_has_no_owner() {
+        assert_eq!(owner(0, 1234), Owner::Nobody);
+    }
+
+    #[test]
+    fn own_process_id_is_the_watcher() {
+        assert_eq!(owner(1234, 1234), Owner::Watcher);
+    }
+
+    #[test]
+    fn other_process_ids_are_kept() {
+        assert_eq!(owner(42, 1234), Owner::Process(42));
+    }
+
+    #[test]
+    fn a_reused_process_id_is_another_process() {
+        let mut values = PerProcess::default();
+        values.insert((42, 1000), "first", |_| true);
+        assert_eq!(values.get(&(42, 1000)), Some(&"first"));
+        assert_eq!(values.get(&(42, 2000)), None);
+    }
+
+    #[test]
+    fn exited_processes_are_dropped_once_the_map_fills() {
+        let mut values = PerProcess::default();
+        let running = |(pid, _): &ProcessKey| pid % 2 == 0;
+        for pid in 0..MIN_PRUNE_AT as u32 {
+            values.insert((pid, 0), (), running);
+        }
+        assert_eq!(values.len(), MIN_PRUNE_AT);
+        values.insert((1000, 0), (), running);
+        assert_eq!(values.len(), MIN_PRUNE_AT / 2 + 1);
+        assert!(values.get(&(1, 0)).is_none());
+        assert!(values.get(&(2, 0)).is_some());
+    }
+
+    #[test]
+    fn running_processes_push_the_next_prune_back() {
+        let mut values = PerProcess::default();
+        let checked = std::cell::Cell::new(0);
+        let running = |_: &ProcessKey| {
+            checked.set(checked.get() + 1);
+            true
+        };
+        for pid in 0..=MIN_PRUNE_AT as u32 {
+            values.insert((pid, 0), (), running);
+        }
+        assert_eq!(checked.get(), MIN_PRUNE_AT);
+        // Nothing exited, so the next prune waits for twice as many.
+        for pid in 1..MIN_PRUNE_AT as u32 {
+            values.insert((1000 + pid, 0), (), running);
+        }
+        assert_eq!(checked.get(), MIN_PRUNE_AT);
+        values.insert((2000, 0), (), running);
+        assert_eq!(checked.get(), MIN_PRUNE_AT + 2 * MIN_PRUNE_AT);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::log::error;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use winsafe::{prelude::*, FILETIME, HPROCESS};
+
+/// Accumulates per-phase timings for `--profile` runs.
+pub struct Profiler {
+    started: Instant,
+    length: Duration,
+    polls: u64,
+    samples: BTreeMap<&'static str, Vec<Duration>>,
+}
+
+impl Profiler {
+    pub fn new(seconds: u64) -> Self {
+        Profiler {
+            started: Instant::now(),
+            length: Duration::from_secs(seconds),
+            polls: 0,
+            samples: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_poll(&mut self) {
+        self.polls += 1;
+    }
+
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.samples.entry(phase).or_default().push(start.elapsed());
+        result
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.started.elapsed() >= self.length
+    }
+
+    pub fn print_report(&self) {
+        let wall = self.started.elapsed().as_secs_f64();
+        println!("Profile over {:.1}s ({} polls)", wall, self.polls);
+        println!(
+            "{:<28} {:>8} {:>12} {:>12}",
+            "phase", "calls", "median", "p95"
+        );
+        for (phase, samples) in &self.samples {
+            let mut sorted = samples.clone();
+            sorted.sort();
+            println!(
+                "{:<28} {:>8} {:>10.3}ms {:>10.3}ms",
+                phase,
+                sorted.len(),
+                percentile(&sorted, 0.5).as_secs_f64() * 1000.0,
+                percentile(&sorted, 0.95).as_secs_f64() * 1000.0,
+            );
+        }
+
+        let heartbeats = self.samples.get("heartbeat").map_or(0, |s| s.len());
+        println!("Heartbeats/sec: {:.3}", heartbeats as f64 / wall);
+
+        match HPROCESS::GetCurrentProcess().GetProcessTimes() {
+            Ok((_, _, kernel, user)) => {
+                let kernel = filetime_secs(&kernel);
+                let user = filetime_secs(&user);
+                println!(
+                    "CPU time: {:.3}s user, {:.3}s kernel ({:.3}% of wall time)",
+                    user,
+                    kernel,
+                    (user + kernel) / wall * 100.0
+                );
+            }
+            Err(e) => error!("Failed to query process times: {}", e),
+        }
+    }
+}
+
+/// Times `f` under `phase` when profiling is enabled.
+pub fn timed<T>(profiler: &mut Option<Profiler>, phase: &'static str, f: impl FnOnce() -> T) -> T {
+    match profiler {
+        Some(p) => p.time(phase, f),
+        None => f(),
+    }
+}
+
+fn percentile(sorted: &[Duration], q: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * q).round() as usize;
+    sorted[index]
+}
+
+/// Converts a FILETIME interval (100ns ticks) to seconds.
+fn filetime_secs(ft: &FILETIME) -> f64 {
+    let ticks = ((ft.dwHighDateTime as u64) << 32) | ft.dwLowDateTime as u64;
+    ticks as f64 / 10_000_000.0
+}
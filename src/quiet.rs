@@ -0,0 +1,109 @@
+use chrono::NaiveTime;
+
+/// A daily range of local time, such as `22:00-07:00`, during which nothing
+/// is tracked.
+#[derive(Clone)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Parses `HH:MM-HH:MM`. A range whose end is before its start crosses
+    /// midnight.
+    pub fn parse(s: &str) -> Result<QuietHours, String> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("'{}' is not a range like 22:00-07:00", s))?;
+        let time = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|_| format!("'{}' is not a time like 07:00", t.trim()))
+        };
+        Ok(QuietHours {
+            start: time(start)?,
+            end: time(end)?,
+        })
+    }
+
+    /// Returns whether the wall-clock time `t` falls in the range. Being
+    /// based on wall-clock time, a range keeps its local times across DST
+    /// changes: its length shrinks or grows by the hour instead.
+    pub fn contains(&self, t: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= t && t < self.end
+        } else {
+            self.start <= t || t < self.end
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, FixedOffset, Utc};
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn contains_times_inside_a_daytime_range() {
+        let quiet = QuietHours::parse("12:00-13:30").unwrap();
+        assert!(quiet.contains(time("12:45")));
+        assert!(!quiet.contains(time("11:59")));
+        assert!(!quiet.contains(time("14:00")));
+    }
+
+    #[test]
+    fn a_range_ending_before_it_starts_crosses_midnight() {
+        let quiet = QuietHours::parse("22:00-06:00").unwrap();
+        assert!(quiet.contains(time("23:00")));
+        assert!(quiet.contains(time("00:00")));
+        assert!(quiet.contains(time("05:59")));
+        assert!(!quiet.contains(time("12:00")));
+        assert!(!quiet.contains(time("21:59")));
+    }
+
+    #[test]
+    fn ranges_include_their_start_but_not_their_end() {
+        for range in ["22:00-06:00", "06:00-22:00"] {
+            let quiet = QuietHours::parse(range).unwrap();
+            let (start, end) = range.split_once('-').unwrap();
+            assert!(quiet.contains(time(start)), "{}", range);
+            assert!(!quiet.contains(time(end)), "{}", range);
+        }
+    }
+
+    #[test]
+    fn an_equal_start_and_end_is_an_empty_range() {
+        let quiet = QuietHours::parse("08:00-08:00").unwrap();
+        for t in ["00:00", "07:59", "08:00", "08:01", "23:59"] {
+            assert!(!quiet.contains(time(t)), "{}", t);
+        }
+    }
+
+    #[test]
+    fn ranges_follow_wall_clock_time_across_dst() {
+        // Clocks in Central Europe went from 02:00 CET (+01:00) to 03:00
+        // CEST (+02:00) on 2024-03-31, at 01:00 UTC.
+        let local = |utc: &str, offset_hours: i32| {
+            utc.parse::<DateTime<Utc>>()
+                .unwrap()
+                .with_timezone(&FixedOffset::east_opt(offset_hours * 3600).unwrap())
+                .time()
+        };
+        let quiet = QuietHours::parse("22:00-06:00").unwrap();
+        assert!(quiet.contains(local("2024-03-30T21:00:00Z", 1)));
+        // The range still ends at 06:00 local time, an hour earlier in UTC.
+        assert!(quiet.contains(local("2024-03-31T03:59:00Z", 2)));
+        assert!(!quiet.contains(local("2024-03-31T04:00:00Z", 2)));
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        assert!(QuietHours::parse("22:00").is_err());
+        assert!(QuietHours::parse("22:00-25:00").is_err());
+        assert!(QuietHours::parse("10pm-6am").is_err());
+        assert!(QuietHours::parse(" 22:00 - 06:00 ").is_ok());
+    }
+}
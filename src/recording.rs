@@ -0,0 +1,17 @@
+use regex::Regex;
+use winsafe::{co, prelude::*, SysResult, HPROCESSLIST};
+
+/// Returns whether any process matching `recorders` is running.
+///
+/// This is a heuristic: a running recorder such as OBS is taken to mean the
+/// screen is being recorded or streamed, whether or not it is capturing.
+pub fn is_recording(recorders: &[Regex]) -> SysResult<bool> {
+    let mut snapshot = HPROCESSLIST::CreateToolhelp32Snapshot(co::TH32CS::SNAPPROCESS, None)?;
+    for process in snapshot.iter_processes() {
+        let name = process?.szExeFile();
+        if recorders.iter().any(|r| r.is_match(&name)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
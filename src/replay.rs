@@ -0,0 +1,184 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::io::BufRead;
+
+/// A window recorded as having come to the foreground at `timestamp`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub timestamp: DateTime<Utc>,
+    pub app: String,
+    pub title: String,
+}
+
+/// Reads one JSON object per line, each with a `timestamp` in RFC 3339 and
+/// an `app` and `title`. These can also be inside `data`, so the output of
+/// `export` can be read as it is. Blank lines are skipped, and the rows are
+/// returned oldest first.
+pub fn read_rows(input: impl BufRead) -> Result<Vec<Row>, String> {
+    let mut rows = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = parse_row(&line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        rows.push(row);
+    }
+    rows.sort_by_key(|row| row.timestamp);
+    Ok(rows)
+}
+
+fn parse_row(line: &str) -> Result<Row, String> {
+    let object = serde_json::from_str::<Map<String, Value>>(line).map_err(|e| e.to_string())?;
+    let data = object.get("data").and_then(Value::as_object);
+    let field = |name: &str| {
+        object
+            .get(name)
+            .or_else(|| data.and_then(|data| data.get(name)))
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("no \"{}\" string", name))
+    };
+    let timestamp = DateTime::parse_from_rfc3339(field("timestamp")?)
+        .map_err(|e| format!("invalid timestamp: {}", e))?;
+    Ok(Row {
+        timestamp: timestamp.with_timezone(&Utc),
+        app: field("app")?.to_string(),
+        title: field("title")?.to_string(),
+    })
+}
+
+/// Plays recorded windows back at the pace they were recorded, without
+/// looking at any real window. The first poll plays the first row, and
+/// each later row comes to the foreground as long after it as it was
+/// recorded. The last row stays in the foreground for one more poll.
+pub struct Replay {
+    rows: VecDeque<Row>,
+    /// How far the recording is shifted to line up with the first poll.
+    offset: Option<Duration>,
+    current: Option<(u32, String, String)>,
+    /// Made-up process ids, one per app in the order they first appear.
+    process_ids: HashMap<String, u32>,
+}
+
+impl Replay {
+    pub fn new(rows: Vec<Row>) -> Self {
+        Replay {
+            rows: rows.into(),
+            offset: None,
+            current: None,
+            process_ids: HashMap::new(),
+        }
+    }
+
+    /// Returns the process id, process name and title of the recorded
+    /// foreground window for a poll at `now`.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Option<(u32, String, String)> {
+        let offset = *self.offset.get_or_insert_with(|| {
+            self.rows
+                .front()
+                .map_or(Duration::zero(), |row| now - row.timestamp)
+        });
+        while self
+            .rows
+            .front()
+            .is_some_and(|row| row.timestamp + offset <= now)
+        {
+            let row = self.rows.pop_front().unwrap();
+            let next = self.process_ids.len() as u32;
+            // Far above the PIDs Windows hands out, so they never collide.
+            let process_id = *self
+                .process_ids
+                .entry(row.app.clone())
+                .or_insert(0x7100_0000 + next);
+            self.current = Some((process_id, row.app, row.title));
+        }
+        self.current.clone()
+    }
+
+    /// Returns whether every row has been played and the last one has had
+    /// its poll, so the replay is over.
+    pub fn finished(&self) -> bool {
+        self.rows.is_empty() && self.current.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn row(seconds: i64, app: &str, title: &str) -> Row {
+        Row {
+            timestamp: time(seconds),
+            app: app.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_rows_and_exported_events() {
+        let input = concat!(
+            r#"{"timestamp":"2023-11-14T22:13:25Z","app":"b.exe","title":"B"}"#,
+            "\n\n",
+            r#"{"bucket":"x","timestamp":"2023-11-14T22:13:20+00:00","duration":5.0,"data":{"app":"a.exe","title":"A"}}"#,
+            "\n",
+        );
+        assert_eq!(
+            read_rows(input.as_bytes()).unwrap(),
+            [row(0, "a.exe", "A"), row(5, "b.exe", "B")]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_row() {
+        let input = concat!(
+            r#"{"timestamp":"2023-11-14T22:13:20Z","app":"a.exe","title":"A"}"#,
+            "\n",
+            r#"{"timestamp":"yesterday","app":"a.exe","title":"A"}"#,
+        );
+        let error = read_rows(input.as_bytes()).unwrap_err();
+        assert!(error.starts_with("line 2: invalid timestamp"), "{}", error);
+        let error = read_rows(r#"{"timestamp":"2023-11-14T22:13:20Z"}"#.as_bytes()).unwrap_err();
+        assert_eq!(error, "line 1: no \"app\" string");
+    }
+
+    #[test]
+    fn plays_rows_at_the_recorded_pace() {
+        let mut replay = Replay::new(vec![
+            row(0, "a.exe", "A"),
+            row(2, "b.exe", "B"),
+            row(3, "a.exe", "C"),
+        ]);
+        let start = time(1000);
+        let poll = |replay: &mut Replay, seconds| {
+            replay
+                .poll(start + Duration::seconds(seconds))
+                .map(|(pid, _, title)| (pid, title))
+        };
+        assert_eq!(poll(&mut replay, 0), Some((0x7100_0000, "A".to_string())));
+        assert_eq!(poll(&mut replay, 1), Some((0x7100_0000, "A".to_string())));
+        assert!(!replay.finished());
+        // Both later rows are due, and the poll sees the last of them.
+        assert_eq!(poll(&mut replay, 3), Some((0x7100_0000, "C".to_string())));
+        assert!(replay.finished());
+    }
+
+    #[test]
+    fn gives_each_app_its_own_process_id() {
+        let mut replay = Replay::new(vec![row(0, "a.exe", "A"), row(1, "b.exe", "B")]);
+        let (a, _, _) = replay.poll(time(0)).unwrap();
+        let (b, _, _) = replay.poll(time(1)).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn an_empty_recording_plays_nothing() {
+        let mut replay = Replay::new(Vec::new());
+        assert_eq!(replay.poll(time(0)), None);
+        assert!(!replay.finished());
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::WatcherError;
+use aw_client_rust::blocking::AwClient;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+
+/// Prints how long each app was active in `buckets` over the last `since`.
+pub fn print(client: &AwClient, buckets: &[String], since: Duration) -> Result<(), WatcherError> {
+    let end = Utc::now();
+    let start = end - since;
+    let mut events = Vec::new();
+    for bucket in buckets {
+        events.extend(client.get_events(bucket, Some(start), Some(end), None)?);
+    }
+
+    let mut totals: HashMap<String, Duration> = HashMap::new();
+    for event in events {
+        let app = match event.data.get("app").and_then(|app| app.as_str()) {
+            Some(app) => app.to_string(),
+            None => continue,
+        };
+        // Events overlapping the start of the range only count from there.
+        let event_end = event.timestamp + event.duration;
+        let active = event_end - event.timestamp.max(start);
+        if active > Duration::zero() {
+            *totals.entry(app).or_insert_with(Duration::zero) += active;
+        }
+    }
+
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total = totals
+        .iter()
+        .fold(Duration::zero(), |sum, (_, active)| sum + *active);
+    for (app, active) in &totals {
+        println!("{:>9}  {}", format_duration(*active), app);
+    }
+    println!("{:>9}  total", format_duration(total));
+    Ok(())
+}
+
+fn format_duration(d: Duration) -> String {
+    let seconds = d.num_seconds();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
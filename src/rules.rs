@@ -0,0 +1,390 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// Decides which windows have their title replaced by the process name.
+pub struct TitleRules {
+    exclude_all: bool,
+    exclude: Vec<Regex>,
+    include: Vec<Regex>,
+}
+
+impl TitleRules {
+    pub fn new(exclude_all: bool, exclude: &[String], include: &[String], substring: bool) -> Self {
+        TitleRules {
+            exclude_all,
+            exclude: compile_patterns(exclude, substring),
+            include: compile_patterns(include, substring),
+        }
+    }
+
+    /// Returns whether the title of a window owned by `process_name` should
+    /// be redacted. Include patterns override both exclusion options.
+    pub fn redacts(&self, process_name: &str) -> bool {
+        (self.exclude_all || self.exclude.iter().any(|r| r.is_match(process_name)))
+            && !self.include.iter().any(|r| r.is_match(process_name))
+    }
+}
+
+/// Returns whether `pattern` is not a valid regex, so `compile_patterns`
+/// matches it literally. A pattern also has to stay valid once anchored to
+/// the whole name, which one ending in a `(?x)` comment does not.
+pub fn is_literal(pattern: &str) -> bool {
+    Regex::new(pattern).is_err() || Regex::new(&anchored(pattern)).is_err()
+}
+
+fn anchored(pattern: &str) -> String {
+    format!("^(?:{})$", pattern)
+}
+
+/// Compiles process name patterns, treating invalid regexes as literals.
+/// Unless `substring` is set, a pattern has to match the whole name.
+pub fn compile_patterns(patterns: &[String], substring: bool) -> Vec<Regex> {
+    patterns
+        .iter()
+        .map(|s| {
+            let pattern = if is_literal(s) {
+                regex::escape(s)
+            } else {
+                s.to_string()
+            };
+            if substring {
+                Regex::new(&pattern).unwrap()
+            } else {
+                Regex::new(&anchored(&pattern)).unwrap()
+            }
+        })
+        .collect()
+}
+
+/// Removes every match of `patterns` from `title`, leaving the part of the
+/// title that decides whether the window changed.
+pub fn significant_title(patterns: &[Regex], title: &str) -> String {
+    patterns.iter().fold(title.to_string(), |title, r| {
+        r.replace_all(&title, "").into_owned()
+    })
+}
+
+/// Returns the part of a change detection key that stays the same while
+/// switching between titles or tabs of one app.
+pub fn app_key(key: &Map<String, Value>) -> Map<String, Value> {
+    let mut app_key = key.clone();
+    app_key.remove("title");
+    app_key.remove("tab");
+    app_key
+}
+
+/// Returns the change detection key for a window. For an app with a stable
+/// title, only switching to another app is a change, so its key is the
+/// app level key.
+pub fn window_key(key: Map<String, Value>, stable_title: bool) -> Map<String, Value> {
+    if stable_title {
+        app_key(&key)
+    } else {
+        key
+    }
+}
+
+/// Removes NUL and other control characters that some apps put in their
+/// titles. Lone surrogates have already been replaced with U+FFFD when the
+/// UTF-16 title was decoded.
+pub fn sanitize_title(title: &str) -> String {
+    title.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Returns how similar two titles are, from 0.0 for nothing in common to 1.0
+/// for equal titles, as one minus their edit distance relative to the longer
+/// title. Takes time proportional to the product of the titles' lengths.
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// Built-in patterns for `scrub_paths`, each with the placeholder that
+/// replaces its matches. URLs come first so that a `file://` URL is replaced
+/// as a whole.
+pub fn path_patterns() -> Vec<(Regex, &'static str)> {
+    [
+        // http://, https://, file:// and other URLs.
+        (r"\b[A-Za-z][A-Za-z0-9+.-]*://\S+", "<url>"),
+        // UNC paths such as \\server\share\file.txt.
+        (r"\\\\[^\\\s]+\\\S*", "<path>"),
+        // Drive paths such as C:\Users\me\file.txt or C:/Users.
+        (r"\b[A-Za-z]:[\\/]\S*", "<path>"),
+        // Home-relative and absolute Unix paths, as in WSL terminal titles.
+        (r"~/\S*|\B/[^\s/]+(?:/[^\s/]+)+/?", "<path>"),
+    ]
+    .into_iter()
+    .map(|(pattern, placeholder)| (Regex::new(pattern).unwrap(), placeholder))
+    .collect()
+}
+
+/// Replaces file paths and URLs in `title` with placeholders. Paths are
+/// taken to end at the first space, so the rest of a path with spaces in it
+/// is left in the title.
+pub fn scrub_paths(patterns: &[(Regex, &str)], title: &str) -> String {
+    patterns
+        .iter()
+        .fold(title.to_string(), |title, (r, placeholder)| {
+            r.replace_all(&title, *placeholder).into_owned()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns_match_the_whole_name() {
+        let regexes = compile_patterns(&patterns(&["Code.exe"]), false);
+        assert!(regexes[0].is_match("Code.exe"));
+        assert!(!regexes[0].is_match("VSCode.exe"));
+        assert!(!regexes[0].is_match("Code.exe.bak"));
+    }
+
+    #[test]
+    fn substring_patterns_match_anywhere() {
+        let regexes = compile_patterns(&patterns(&["Code.exe"]), true);
+        assert!(regexes[0].is_match("Code.exe"));
+        assert!(regexes[0].is_match("VSCode.exe"));
+    }
+
+    #[test]
+    fn alternations_are_anchored_as_a_whole() {
+        let regexes = compile_patterns(&patterns(&["Code|WINWORD"]), false);
+        assert!(regexes[0].is_match("WINWORD"));
+        assert!(!regexes[0].is_match("VSCode"));
+        assert!(!regexes[0].is_match("WINWORD.EXE"));
+    }
+
+    #[test]
+    fn invalid_patterns_match_literally() {
+        assert!(is_literal("setup (1.exe"));
+        let regexes = compile_patterns(&patterns(&["setup (1.exe"]), false);
+        assert!(regexes[0].is_match("setup (1.exe"));
+        assert!(!regexes[0].is_match("setup 1.exe"));
+    }
+
+    #[test]
+    fn patterns_broken_by_anchoring_match_literally() {
+        // The comment would swallow the closing `)$` of the anchored regex.
+        assert!(is_literal("(?x)app#note"));
+        for substring in [false, true] {
+            let regexes = compile_patterns(&patterns(&["(?x)app#note"]), substring);
+            assert!(regexes[0].is_match("(?x)app#note"));
+            assert!(!regexes[0].is_match("app"));
+        }
+    }
+
+    #[test]
+    fn sanitize_title_strips_control_characters() {
+        assert_eq!(sanitize_title("Untitled\0 - Notepad"), "Untitled - Notepad");
+        assert_eq!(sanitize_title("a\tb\r\nc\u{7f}d\u{9b}e"), "abcde");
+        assert_eq!(sanitize_title("\0\0\0"), "");
+    }
+
+    #[test]
+    fn sanitize_title_keeps_other_text() {
+        assert_eq!(sanitize_title("日本語 — ✓ 🎉"), "日本語 — ✓ 🎉");
+    }
+
+    #[test]
+    fn lone_surrogates_become_replacement_characters() {
+        // "a", a lone high surrogate, "b", a lone low surrogate, as a title
+        // would come back from GetWindowText.
+        let utf16 = [0x61, 0xD800, 0x62, 0xDC00];
+        let title = sanitize_title(&String::from_utf16_lossy(&utf16));
+        assert_eq!(title, "a\u{FFFD}b\u{FFFD}");
+        assert_eq!(
+            serde_json::to_string(&title).unwrap(),
+            "\"a\u{FFFD}b\u{FFFD}\""
+        );
+    }
+
+    fn title_rules(exclude_all: bool, redact: &[&str], keep: &[&str]) -> TitleRules {
+        TitleRules::new(exclude_all, &patterns(redact), &patterns(keep), false)
+    }
+
+    #[test]
+    fn keep_title_for_takes_precedence() {
+        let rules = title_rules(true, &["Code.exe"], &["Code.exe"]);
+        assert!(!rules.redacts("Code.exe"));
+        assert!(rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn redact_title_for_redacts_only_matches() {
+        let rules = title_rules(false, &["Code.exe"], &[]);
+        assert!(rules.redacts("Code.exe"));
+        assert!(!rules.redacts("VSCode.exe"));
+        assert!(!rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn exclude_title_redacts_everything_not_kept() {
+        let rules = title_rules(true, &[], &["firefox.exe"]);
+        assert!(rules.redacts("Code.exe"));
+        assert!(!rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn no_rules_redact_nothing() {
+        assert!(!title_rules(false, &[], &[]).redacts("Code.exe"));
+    }
+
+    #[test]
+    fn keep_title_for_alone_is_a_no_op() {
+        let rules = title_rules(false, &[], &["Code.exe"]);
+        assert!(!rules.redacts("Code.exe"));
+        assert!(!rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn exclude_title_alone_redacts_everything() {
+        let rules = title_rules(true, &[], &[]);
+        assert!(rules.redacts("Code.exe"));
+        assert!(rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn keep_title_for_overrides_redact_title_for() {
+        let rules = title_rules(false, &[r".*\.exe"], &["firefox.exe"]);
+        assert!(rules.redacts("Code.exe"));
+        assert!(!rules.redacts("firefox.exe"));
+        assert!(!rules.redacts("explorer"));
+    }
+
+    #[test]
+    fn redact_title_for_with_exclude_title_redacts_everything() {
+        let rules = title_rules(true, &["Code.exe"], &[]);
+        assert!(rules.redacts("Code.exe"));
+        assert!(rules.redacts("firefox.exe"));
+    }
+
+    #[test]
+    fn substring_rules_match_anywhere() {
+        let rules = TitleRules::new(false, &patterns(&["Code"]), &patterns(&["VS"]), true);
+        assert!(rules.redacts("Code.exe"));
+        assert!(!rules.redacts("VSCode.exe"));
+    }
+
+    fn scrub(title: &str) -> String {
+        scrub_paths(&path_patterns(), title)
+    }
+
+    #[test]
+    fn scrub_paths_replaces_drive_paths() {
+        assert_eq!(scrub(r"C:\Users\x\a.txt - Notepad"), "<path> - Notepad");
+        assert_eq!(scrub("C:/Users/x - Explorer"), "<path> - Explorer");
+    }
+
+    #[test]
+    fn scrub_paths_replaces_unc_paths() {
+        assert_eq!(scrub(r"\\srv\share"), "<path>");
+        assert_eq!(
+            scrub(r"report.docx on \\srv\share\docs"),
+            "report.docx on <path>"
+        );
+    }
+
+    #[test]
+    fn scrub_paths_replaces_urls() {
+        assert_eq!(
+            scrub("https://example.com/a?b=c - Mozilla Firefox"),
+            "<url> - Mozilla Firefox"
+        );
+        assert_eq!(scrub("file:///C:/Users/x/a.html"), "<url>");
+    }
+
+    #[test]
+    fn scrub_paths_replaces_unix_paths() {
+        assert_eq!(scrub("user@host: ~/src/app"), "user@host: <path>");
+        assert_eq!(scrub("vim /etc/hosts"), "vim <path>");
+    }
+
+    #[test]
+    fn scrub_paths_keeps_titles_without_paths() {
+        for title in [
+            "Inbox - 3/4 unread",
+            "Meeting notes and/or agenda",
+            "A/B test results - Excel",
+            "Untitled - Notepad",
+        ] {
+            assert_eq!(scrub(title), title);
+        }
+    }
+
+    /// Returns the windows each poll is reported as, merging polls whose
+    /// key did not change.
+    fn events(stable_title_for: &[&str], polls: &[(&str, &str)]) -> Vec<String> {
+        let stable_title = compile_patterns(&patterns(stable_title_for), false);
+        let mut events = Vec::new();
+        let mut prev_key = None;
+        for (app, title) in polls {
+            let mut data = Map::new();
+            data.insert("app".to_string(), Value::from(*app));
+            data.insert("title".to_string(), Value::from(*title));
+            let stable = stable_title.iter().any(|r| r.is_match(app));
+            let key = window_key(data, stable);
+            if prev_key.as_ref() != Some(&key) {
+                events.push(format!("{} {}", app, title));
+            }
+            prev_key = Some(key);
+        }
+        events
+    }
+
+    #[test]
+    fn stable_title_changes_stay_one_event() {
+        let polls = [
+            ("Code.exe", "a.rs"),
+            ("Code.exe", "b.rs"),
+            ("firefox.exe", "Inbox"),
+            ("firefox.exe", "News"),
+            ("Code.exe", "c.rs"),
+            ("Code.exe", "a.rs"),
+            ("firefox.exe", "Inbox"),
+        ];
+        assert_eq!(
+            events(&["Code.exe"], &polls),
+            [
+                "Code.exe a.rs",
+                "firefox.exe Inbox",
+                "firefox.exe News",
+                "Code.exe c.rs",
+                "firefox.exe Inbox",
+            ]
+        );
+        assert_eq!(events(&[], &polls).len(), polls.len());
+    }
+
+    #[test]
+    fn app_key_leaves_out_title_and_tab() {
+        let mut key = Map::new();
+        key.insert("app".to_string(), Value::from("WindowsTerminal.exe"));
+        key.insert("title".to_string(), Value::from("pwsh"));
+        key.insert("tab".to_string(), Value::from("pwsh"));
+        let app_key = app_key(&key);
+        assert_eq!(app_key.len(), 1);
+        assert_eq!(app_key["app"], "WindowsTerminal.exe");
+        assert_eq!(window_key(key.clone(), false), key);
+    }
+}
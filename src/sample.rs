@@ -0,0 +1,222 @@
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+
+/// A change detection key, as built by the watcher from event data.
+type Key = Map<String, Value>;
+
+/// Collects polls over a sampling window and picks the window that was in
+/// the foreground for most of them.
+pub struct Sampler<T> {
+    length: Duration,
+    started: Option<Instant>,
+    /// The data first seen for each change detection key, and how many
+    /// polls saw that key.
+    samples: Vec<(T, Map<String, Value>, u32)>,
+}
+
+impl<T> Sampler<T> {
+    pub fn new(length_ms: u32) -> Self {
+        Sampler {
+            length: Duration::from_millis(length_ms.into()),
+            started: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Records one poll. Once the sampling window has passed, returns the
+    /// data and key seen by the most polls and starts a new window. Ties go
+    /// to the window seen first.
+    pub fn record(&mut self, data: T, key: Map<String, Value>) -> Option<(T, Map<String, Value>)> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        match self.samples.iter_mut().find(|(_, k, _)| *k == key) {
+            Some((_, _, count)) => *count += 1,
+            None => self.samples.push((data, key, 1)),
+        }
+        if started.elapsed() < self.length {
+            return None;
+        }
+
+        self.started = None;
+        // max_by_key keeps the last of equal elements, so iterate in reverse.
+        let best = self
+            .samples
+            .drain(..)
+            .rev()
+            .max_by_key(|(_, _, count)| *count);
+        best.map(|(data, key, _)| (data, key))
+    }
+}
+
+/// Collects polls over a window and picks the app that was in the
+/// foreground for most of them, with the title it had most often.
+pub struct Coalescer<T> {
+    length: Duration,
+    started: Option<Instant>,
+    /// The data first seen for each change detection key, with the app
+    /// level key it belongs to and how many polls saw it.
+    samples: Vec<(T, Key, Key, u32)>,
+}
+
+impl<T> Coalescer<T> {
+    pub fn new(length_ms: u32) -> Self {
+        Coalescer {
+            length: Duration::from_millis(length_ms.into()),
+            started: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Records one poll. Once the window has passed, returns the data with
+    /// the most polls among those of the app with the most polls, together
+    /// with the app level key, and starts a new window. Ties go to what was
+    /// seen first.
+    pub fn record(
+        &mut self,
+        data: T,
+        app_key: Map<String, Value>,
+        key: Map<String, Value>,
+    ) -> Option<(T, Map<String, Value>)> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        match self.samples.iter_mut().find(|(_, _, k, _)| *k == key) {
+            Some((_, _, _, count)) => *count += 1,
+            None => self.samples.push((data, app_key, key, 1)),
+        }
+        if started.elapsed() < self.length {
+            return None;
+        }
+
+        self.started = None;
+        let mut apps: Vec<(&Map<String, Value>, u32)> = Vec::new();
+        for (_, app_key, _, count) in &self.samples {
+            match apps.iter_mut().find(|(k, _)| *k == app_key) {
+                Some((_, total)) => *total += count,
+                None => apps.push((app_key, *count)),
+            }
+        }
+        // max_by_key keeps the last of equal elements, so iterate in reverse.
+        let app = apps
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, total)| *total)?
+            .0
+            .clone();
+        let best = self
+            .samples
+            .drain(..)
+            .filter(|(_, app_key, _, _)| *app_key == app)
+            .rev()
+            .max_by_key(|(_, _, _, count)| *count);
+        best.map(|(data, app_key, _, _)| (data, app_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Long enough that the polls recorded one after another never fill
+    /// a window by themselves.
+    const WINDOW_MS: u32 = 200;
+
+    fn key(app: &str, title: &str) -> Key {
+        let mut key = Map::new();
+        key.insert("app".to_string(), Value::String(app.to_string()));
+        key.insert("title".to_string(), Value::String(title.to_string()));
+        key
+    }
+
+    fn app_key(app: &str) -> Key {
+        let mut key = key(app, "");
+        key.remove("title");
+        key
+    }
+
+    fn wait_for_window() {
+        thread::sleep(Duration::from_millis(WINDOW_MS.into()));
+    }
+
+    #[test]
+    fn sampler_picks_the_window_seen_most() {
+        let mut sampler = Sampler::new(WINDOW_MS);
+        assert_eq!(sampler.record("a", key("a.exe", "A")), None);
+        assert_eq!(sampler.record("b", key("b.exe", "B")), None);
+        assert_eq!(sampler.record("b", key("b.exe", "B")), None);
+        wait_for_window();
+        assert_eq!(
+            sampler.record("c", key("c.exe", "C")),
+            Some(("b", key("b.exe", "B")))
+        );
+    }
+
+    #[test]
+    fn sampler_ties_go_to_the_window_seen_first() {
+        let mut sampler = Sampler::new(WINDOW_MS);
+        sampler.record("a", key("a.exe", "A"));
+        sampler.record("b", key("b.exe", "B"));
+        sampler.record("b", key("b.exe", "B"));
+        wait_for_window();
+        assert_eq!(
+            sampler.record("a", key("a.exe", "A")),
+            Some(("a", key("a.exe", "A")))
+        );
+    }
+
+    #[test]
+    fn sampler_keeps_the_first_data_for_a_key() {
+        let mut sampler = Sampler::new(WINDOW_MS);
+        sampler.record("first", key("a.exe", "A"));
+        wait_for_window();
+        assert_eq!(
+            sampler.record("second", key("a.exe", "A")),
+            Some(("first", key("a.exe", "A")))
+        );
+    }
+
+    #[test]
+    fn sampler_starts_a_new_window_after_each_pick() {
+        let mut sampler = Sampler::new(WINDOW_MS);
+        // The window starts with the first poll, not with the sampler.
+        wait_for_window();
+        assert!(sampler.record("a", key("a.exe", "A")).is_none());
+        wait_for_window();
+        assert_eq!(
+            sampler.record("b", key("b.exe", "B")),
+            Some(("a", key("a.exe", "A")))
+        );
+        assert_eq!(sampler.record("b", key("b.exe", "B")), None);
+    }
+
+    #[test]
+    fn coalescer_picks_the_app_seen_most_then_its_title() {
+        let mut coalescer = Coalescer::new(WINDOW_MS);
+        // b.exe has the single most seen title, but a.exe the most polls.
+        for (app, title) in [
+            ("a.exe", "1"),
+            ("b.exe", "B"),
+            ("b.exe", "B"),
+            ("a.exe", "2"),
+        ] {
+            assert_eq!(coalescer.record(title, app_key(app), key(app, title)), None);
+        }
+        wait_for_window();
+        assert_eq!(
+            coalescer.record("2", app_key("a.exe"), key("a.exe", "2")),
+            Some(("2", app_key("a.exe")))
+        );
+    }
+
+    #[test]
+    fn coalescer_ties_go_to_what_was_seen_first() {
+        let mut coalescer = Coalescer::new(WINDOW_MS);
+        for (app, title) in [("a.exe", "1"), ("b.exe", "B"), ("b.exe", "B")] {
+            coalescer.record(title, app_key(app), key(app, title));
+        }
+        wait_for_window();
+        // a.exe ties with b.exe, and its titles tie with each other.
+        assert_eq!(
+            coalescer.record("2", app_key("a.exe"), key("a.exe", "2")),
+            Some(("1", app_key("a.exe")))
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use std::mem::size_of;
+use std::time::Duration;
+use windows::Win32::System::StationsAndDesktops::{
+    CloseDesktop, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_READOBJECTS,
+};
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+/// Returns whether there is an interactive desktop receiving user input.
+/// There is none in a service session before anyone logs on or in a
+/// headless session, and GetForegroundWindow returns stale windows then.
+pub fn has_input_desktop() -> bool {
+    unsafe {
+        match OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_READOBJECTS) {
+            Ok(desktop) => {
+                let _ = CloseDesktop(desktop);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Returns how long ago the last keyboard or mouse input in this session
+/// was, or None if it cannot be read.
+pub fn idle_time() -> Option<Duration> {
+    let mut info = LASTINPUTINFO {
+        cbSize: size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    unsafe {
+        if !GetLastInputInfo(&mut info).as_bool() {
+            return None;
+        }
+        // Both are tick counts that wrap around every 49.7 days.
+        let idle = GetTickCount().wrapping_sub(info.dwTime);
+        Some(Duration::from_millis(idle.into()))
+    }
+}
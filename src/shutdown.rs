@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::{BOOL, FALSE};
+use windows::Win32::System::Console::{
+    SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT, CTRL_SHUTDOWN_EVENT,
+};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static DONE: AtomicBool = AtomicBool::new(false);
+/// How long the handler waits for the final flush before exiting anyway.
+static WAIT_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Handles Ctrl+C and console close by asking the poll loop to close the
+/// current event and flush queued heartbeats. The process exits once that
+/// is done, or after `wait` at the latest.
+pub fn install_handler(wait: Duration) {
+    WAIT_MS.store(wait.as_millis() as u64, Ordering::SeqCst);
+    if let Err(e) = unsafe { SetConsoleCtrlHandler(Some(on_ctrl), true) } {
+        crate::log::error!("Failed to install the Ctrl+C handler: {}", e);
+    }
+}
+
+/// Returns whether the watcher has been asked to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Tells the handler that the final flush is done and the process can exit.
+pub fn finish() {
+    DONE.store(true, Ordering::SeqCst);
+}
+
+unsafe extern "system" fn on_ctrl(ctrl_type: u32) -> BOOL {
+    if !matches!(
+        ctrl_type,
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT | CTRL_SHUTDOWN_EVENT
+    ) {
+        return FALSE;
+    }
+    REQUESTED.store(true, Ordering::SeqCst);
+    // Windows ends the process as soon as this returns for console close
+    // and shutdown, so wait here rather than in the poll loop.
+    let deadline = Instant::now() + Duration::from_millis(WAIT_MS.load(Ordering::SeqCst));
+    while !DONE.load(Ordering::SeqCst) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+    if !DONE.load(Ordering::SeqCst) {
+        crate::log::warn!("Final heartbeats were not sent in time. Exiting without them");
+    }
+    std::process::exit(0)
+}
@@ -0,0 +1,55 @@
+use rand::Rng;
+
+const APPS: &[&str] = &[
+    "sim-editor.exe",
+    "sim-browser.exe",
+    "sim-terminal.exe",
+    "sim-chat.exe",
+    "sim-mail.exe",
+    "sim-player.exe",
+];
+/// How many different titles each simulated app cycles through, so that
+/// switches sometimes return to a window seen before.
+const TITLES_PER_APP: u32 = 20;
+
+/// Synthesizes foreground window switches for load testing, without
+/// looking at any real window.
+pub struct Simulator {
+    /// Average switches per second.
+    rate: f64,
+    current: (u32, String, String),
+}
+
+impl Simulator {
+    pub fn new(rate: f64) -> Self {
+        Simulator {
+            rate,
+            current: random_window(),
+        }
+    }
+
+    /// Returns the process id, process name and title of the simulated
+    /// foreground window for a poll `poll_ms` after the previous one.
+    /// Switches are random with the configured average rate, but at most
+    /// one happens per poll.
+    pub fn poll(&mut self, poll_ms: u32) -> (u32, String, String) {
+        let expected = self.rate * f64::from(poll_ms) / 1000.0;
+        let p = 1.0 - (-expected).exp();
+        if rand::thread_rng().gen_bool(p.clamp(0.0, 1.0)) {
+            self.current = random_window();
+        }
+        self.current.clone()
+    }
+}
+
+fn random_window() -> (u32, String, String) {
+    let mut rng = rand::thread_rng();
+    let app = rng.gen_range(0..APPS.len());
+    let title = rng.gen_range(0..TITLES_PER_APP);
+    (
+        // Far above the PIDs Windows hands out, so they never collide.
+        0x7000_0000 + app as u32,
+        APPS[app].to_string(),
+        format!("Simulated window {}", title),
+    )
+}
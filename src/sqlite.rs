@@ -0,0 +1,149 @@
+use crate::error::WatcherError;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A local SQLite database that events are written to with the same
+/// heartbeat merging as aw-server, so it can be used without a server.
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it and its table if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS events (
+                id INTEGER PRIMARY KEY,
+                bucket TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                duration REAL NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS events_bucket_timestamp ON events (bucket, timestamp);",
+        )?;
+        Ok(Store {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Extends the bucket's last event with `event` if they have the same
+    /// data and are at most `pulsetime` seconds apart, like aw-server does.
+    /// Otherwise `event` is stored as a new event.
+    pub fn heartbeat(
+        &self,
+        bucket: &str,
+        event: &aw_client_rust::Event,
+        pulsetime: f64,
+    ) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        let last = connection
+            .query_row(
+                "SELECT id, timestamp, duration, data FROM events
+                 WHERE bucket = ?1 ORDER BY timestamp DESC, id DESC LIMIT 1",
+                params![bucket],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, f64>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        if let Some((id, timestamp, duration, data)) = last {
+            let same_data = serde_json::from_str::<Map<String, Value>>(&data)
+                .is_ok_and(|data| data == event.data);
+            if let (true, Ok(start)) = (same_data, DateTime::parse_from_rfc3339(&timestamp)) {
+                let start = start.with_timezone(&Utc);
+                let end = start + from_secs(duration);
+                if event.timestamp >= start && event.timestamp <= end + from_secs(pulsetime) {
+                    let new_end = end.max(event.timestamp + event.duration);
+                    connection.execute(
+                        "UPDATE events SET duration = ?1 WHERE id = ?2",
+                        params![to_secs(new_end - start), id],
+                    )?;
+                    return Ok(());
+                }
+            }
+        }
+        insert(&connection, bucket, event)
+    }
+
+    /// Stores `event` as it is, without merging.
+    pub fn insert_event(
+        &self,
+        bucket: &str,
+        event: &aw_client_rust::Event,
+    ) -> rusqlite::Result<()> {
+        insert(&self.connection.lock().unwrap(), bucket, event)
+    }
+
+    /// Returns every stored event that ends after `since`, with its bucket,
+    /// in the order they happened.
+    pub fn events(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<(String, aw_client_rust::Event)>, WatcherError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT bucket, timestamp, duration, data FROM events
+             WHERE ?1 IS NULL OR julianday(timestamp) + duration / 86400.0 >= julianday(?1)
+             ORDER BY timestamp, id",
+        )?;
+        let since = since.map(|since| since.to_rfc3339_opts(SecondsFormat::Micros, true));
+        let mut rows = statement.query(params![since])?;
+        let mut events = Vec::new();
+        while let Some(row) = rows.next()? {
+            let timestamp = DateTime::parse_from_rfc3339(&row.get::<_, String>(1)?)
+                .map_err(|e| conversion_error(1, e))?;
+            let event = aw_client_rust::Event {
+                id: None,
+                timestamp: timestamp.with_timezone(&Utc),
+                duration: from_secs(row.get(2)?),
+                data: serde_json::from_str(&row.get::<_, String>(3)?)
+                    .map_err(|e| conversion_error(3, e))?,
+            };
+            events.push((row.get(0)?, event));
+        }
+        Ok(events)
+    }
+}
+
+/// Reports a text column that SQLite returned but that does not parse.
+fn conversion_error(
+    column: usize,
+    e: impl std::error::Error + Send + Sync + 'static,
+) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(e))
+}
+
+fn insert(
+    connection: &Connection,
+    bucket: &str,
+    event: &aw_client_rust::Event,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO events (bucket, timestamp, duration, data) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            bucket,
+            event.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            to_secs(event.duration),
+            Value::Object(event.data.clone()).to_string(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn to_secs(duration: Duration) -> f64 {
+    duration.num_microseconds().unwrap_or(i64::MAX) as f64 / 1_000_000.0
+}
+
+fn from_secs(secs: f64) -> Duration {
+    Duration::microseconds((secs * 1_000_000.0) as i64)
+}
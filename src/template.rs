@@ -0,0 +1,123 @@
+use serde_json::{Map, Value};
+
+/// Fields a label template may reference.
+pub const FIELDS: &[&str] = &["app", "title", "tab", "desktop", "user", "tz"];
+
+#[derive(Clone)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// A label template such as `{app}: {title}`.
+#[derive(Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a template, rejecting unknown fields and unbalanced braces.
+    pub fn parse(s: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched '}}' in '{}'", s));
+            }
+            let close = match rest[open..].find('}') {
+                Some(close) => open + close,
+                None => return Err(format!("unclosed '{{' in '{}'", s)),
+            };
+            let field = &rest[open + 1..close];
+            if !FIELDS.contains(&field) {
+                return Err(format!(
+                    "unknown field '{{{}}}', expected one of {}",
+                    field,
+                    FIELDS.join(", ")
+                ));
+            }
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            parts.push(Part::Field(field.to_string()));
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Fills in the template from `data`. Fields missing from `data` are
+    /// left empty.
+    pub fn render(&self, data: &Map<String, Value>) -> String {
+        let mut label = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => label.push_str(text),
+                Part::Field(field) => {
+                    if let Some(Value::String(value)) = data.get(field) {
+                        label.push_str(value);
+                    }
+                }
+            }
+        }
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(fields: &[(&str, &str)]) -> Map<String, Value> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn renders_fields_and_text() {
+        let template = Template::parse("{app}: {title} ({tab})").unwrap();
+        let data = data(&[("app", "Code.exe"), ("title", "main.rs"), ("tab", "src")]);
+        assert_eq!(template.render(&data), "Code.exe: main.rs (src)");
+    }
+
+    #[test]
+    fn missing_fields_render_empty() {
+        let template = Template::parse("[{desktop}] {app}").unwrap();
+        assert_eq!(
+            template.render(&data(&[("app", "Code.exe")])),
+            "[] Code.exe"
+        );
+    }
+
+    #[test]
+    fn templates_without_fields_render_as_written() {
+        let template = Template::parse("working").unwrap();
+        assert_eq!(template.render(&Map::new()), "working");
+        assert_eq!(Template::parse("").unwrap().render(&Map::new()), "");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert_eq!(
+            Template::parse("{app} {path}").err().unwrap(),
+            "unknown field '{path}', expected one of app, title, tab, desktop, user, tz"
+        );
+        assert!(Template::parse("{}").is_err());
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        assert_eq!(
+            Template::parse("{app").err().unwrap(),
+            "unclosed '{' in '{app'"
+        );
+        assert_eq!(
+            Template::parse("app}").err().unwrap(),
+            "unmatched '}' in 'app}'"
+        );
+    }
+}
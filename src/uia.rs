@@ -0,0 +1,139 @@
+use crate::com;
+use windows::core::{Error, Interface, Result, VARIANT};
+use windows::Win32::Foundation::{E_NOINTERFACE, HWND as RawHwnd};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+use windows::Win32::UI::Accessibility::{
+    AccessibleObjectFromWindow, CUIAutomation, IAccessible, IUIAutomation, IUIAutomation2,
+    IUIAutomationCondition, IUIAutomationSelectionItemPattern, IUIAutomationTextPattern,
+    IUIAutomationTextRange, TextPatternRangeEndpoint, TextPatternRangeEndpoint_End,
+    TextPatternRangeEndpoint_Start, TextUnit_Character, TreeScope_Descendants,
+    UIA_ControlTypePropertyId, UIA_SelectionItemPatternId, UIA_TabItemControlTypeId,
+    UIA_TextPatternId,
+};
+use windows::Win32::UI::WindowsAndMessaging::{CHILDID_SELF, OBJID_WINDOW};
+use winsafe::{prelude::*, HWND};
+
+/// UI Automation queries for details that the window title does not carry.
+/// COM is initialized on the calling thread, so create one per poll worker.
+pub struct Uia {
+    automation: IUIAutomation,
+    tab_item: IUIAutomationCondition,
+}
+
+const CONNECTION_TIMEOUT_MS: u32 = 500;
+const TRANSACTION_TIMEOUT_MS: u32 = 1000;
+
+impl Uia {
+    pub fn new() -> Result<Self> {
+        com::initialize()?;
+        unsafe {
+            let automation: IUIAutomation =
+                CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)?;
+            // The defaults let a provider that stopped responding stall a
+            // poll for up to 20 seconds. Windows 7 lacks IUIAutomation2 and
+            // keeps them.
+            if let Ok(automation) = automation.cast::<IUIAutomation2>() {
+                let _ = automation.SetConnectionTimeout(CONNECTION_TIMEOUT_MS);
+                let _ = automation.SetTransactionTimeout(TRANSACTION_TIMEOUT_MS);
+            }
+            let tab_item = automation.CreatePropertyCondition(
+                UIA_ControlTypePropertyId,
+                &VARIANT::from(UIA_TabItemControlTypeId.0),
+            )?;
+            Ok(Uia {
+                automation,
+                tab_item,
+            })
+        }
+    }
+
+    /// Returns the name of the selected tab inside `hwnd`, if it has tabs.
+    pub fn selected_tab(&self, hwnd: &HWND) -> Result<Option<String>> {
+        unsafe {
+            let root = self.automation.ElementFromHandle(RawHwnd(hwnd.ptr()))?;
+            let tabs = root.FindAll(TreeScope_Descendants, &self.tab_item)?;
+            for i in 0..tabs.Length()? {
+                let tab = tabs.GetElement(i)?;
+                let selection = tab.GetCurrentPatternAs::<IUIAutomationSelectionItemPattern>(
+                    UIA_SelectionItemPatternId,
+                )?;
+                if selection.CurrentIsSelected()?.as_bool() {
+                    return Ok(Some(tab.CurrentName()?.to_string()));
+                }
+            }
+            Ok(None)
+        }
+    }
+
+    /// Returns how many characters are selected in the focused control, or
+    /// None if it has no text selection to query. The length is measured
+    /// by moving range endpoints, so the selected text is never read.
+    pub fn selection_len(&self) -> Result<Option<usize>> {
+        unsafe {
+            let focused = self.automation.GetFocusedElement()?;
+            let text =
+                match focused.GetCurrentPatternAs::<IUIAutomationTextPattern>(UIA_TextPatternId) {
+                    Ok(text) => text,
+                    Err(_) => return Ok(None),
+                };
+            let ranges = text.GetSelection()?;
+            let mut len = 0;
+            for i in 0..ranges.Length()? {
+                let range = ranges.GetElement(i)?;
+                // A collapsed range is just the caret.
+                if range.CompareEndpoints(
+                    TextPatternRangeEndpoint_Start,
+                    &range,
+                    TextPatternRangeEndpoint_End,
+                )? == 0
+                {
+                    continue;
+                }
+                let from_start = characters_to_end(&range, TextPatternRangeEndpoint_Start)?;
+                let from_end = characters_to_end(&range, TextPatternRangeEndpoint_End)?;
+                len += from_start.saturating_sub(from_end);
+            }
+            Ok(Some(len))
+        }
+    }
+
+    /// Returns the UI Automation name of `hwnd`, which some apps set without
+    /// giving the window a caption.
+    pub fn window_name(&self, hwnd: &HWND) -> Result<String> {
+        unsafe {
+            let element = self.automation.ElementFromHandle(RawHwnd(hwnd.ptr()))?;
+            Ok(element.CurrentName()?.to_string())
+        }
+    }
+
+    /// Returns the MSAA name of `hwnd`, for older apps that only implement
+    /// IAccessible. Lives here because it needs COM initialized too.
+    pub fn msaa_name(&self, hwnd: &HWND) -> Result<String> {
+        unsafe {
+            let mut accessible: Option<IAccessible> = None;
+            AccessibleObjectFromWindow(
+                RawHwnd(hwnd.ptr()),
+                OBJID_WINDOW.0 as u32,
+                &IAccessible::IID,
+                &mut accessible as *mut _ as *mut _,
+            )?;
+            let accessible = accessible.ok_or_else(|| Error::from(E_NOINTERFACE))?;
+            Ok(accessible
+                .get_accName(&VARIANT::from(CHILDID_SELF as i32))?
+                .to_string())
+        }
+    }
+}
+
+/// Returns how many characters lie between `endpoint` of `range` and the end
+/// of the document, by moving a copy of the range there.
+unsafe fn characters_to_end(
+    range: &IUIAutomationTextRange,
+    endpoint: TextPatternRangeEndpoint,
+) -> Result<usize> {
+    let probe = range.Clone()?;
+    probe.MoveEndpointByRange(TextPatternRangeEndpoint_Start, range, endpoint)?;
+    let moved =
+        probe.MoveEndpointByUnit(TextPatternRangeEndpoint_Start, TextUnit_Character, i32::MAX)?;
+    Ok(moved.max(0) as usize)
+}
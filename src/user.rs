@@ -0,0 +1,114 @@
+use crate::log::error;
+use std::ffi::c_void;
+use std::mem::size_of_val;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Security::{
+    GetTokenInformation, IsWellKnownSid, TokenUser, WinLocalSystemSid, TOKEN_QUERY, TOKEN_USER,
+};
+use windows::Win32::System::RemoteDesktop::{
+    WTSFreeMemory, WTSGetActiveConsoleSessionId, WTSQuerySessionInformationW, WTSUserName,
+    WTS_CURRENT_SERVER_HANDLE,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Looks up the name of the user whose activity is being watched. When the
+/// watcher runs as SYSTEM, this is the user logged on to the console
+/// session. The name is looked up again only when the console session
+/// changes, so a failed lookup is reported once per session. A session
+/// nobody has logged on to yet is asked again on every call.
+pub struct Users {
+    local_system: bool,
+    /// The console session of the last lookup and the name it found.
+    cached: Option<(u32, Option<String>)>,
+}
+
+impl Users {
+    pub fn new() -> Self {
+        Users {
+            local_system: is_local_system(),
+            cached: None,
+        }
+    }
+
+    pub fn current(&mut self) -> Option<String> {
+        // The watcher's own user never changes, so any session will do.
+        let session = if self.local_system {
+            unsafe { WTSGetActiveConsoleSessionId() }
+        } else {
+            0
+        };
+        match &self.cached {
+            Some((cached, name)) if *cached == session => name.clone(),
+            _ => {
+                let lookup = if self.local_system {
+                    console_user(session)
+                } else {
+                    winsafe::GetUserName()
+                        .map(Some)
+                        .map_err(|e| format!("Failed to get user name: {}", e))
+                };
+                let name = match lookup {
+                    Ok(None) => return None,
+                    Ok(name) => name,
+                    Err(e) => {
+                        error!("{}", e);
+                        None
+                    }
+                };
+                self.cached = Some((session, name.clone()));
+                name
+            }
+        }
+    }
+}
+
+/// Returns whether the watcher runs as LocalSystem. The account's name is
+/// localized, so its well-known SID is compared instead.
+fn is_local_system() -> bool {
+    unsafe {
+        let mut token = HANDLE::default();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_err() {
+            return false;
+        }
+        // Room for a TOKEN_USER and the longest SID, aligned for the former.
+        let mut buffer = [0u64; 16];
+        let mut len = 0;
+        let queried = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as *mut c_void),
+            size_of_val(&buffer) as u32,
+            &mut len,
+        );
+        let _ = CloseHandle(token);
+        if queried.is_err() {
+            return false;
+        }
+        let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+        IsWellKnownSid(user.User.Sid, WinLocalSystemSid).as_bool()
+    }
+}
+
+/// Returns the user logged on to `session`, or None if nobody is.
+fn console_user(session: u32) -> Result<Option<String>, String> {
+    if session == u32::MAX {
+        return Ok(None);
+    }
+    unsafe {
+        let mut buffer = PWSTR::null();
+        let mut bytes = 0;
+        if let Err(e) = WTSQuerySessionInformationW(
+            WTS_CURRENT_SERVER_HANDLE,
+            session,
+            WTSUserName,
+            &mut buffer,
+            &mut bytes,
+        ) {
+            return Err(format!("Failed to get the console session user: {}", e));
+        }
+        let name = buffer.to_string().ok();
+        WTSFreeMemory(buffer.0 as *mut c_void);
+        Ok(name.filter(|name| !name.is_empty()))
+    }
+}
@@ -0,0 +1,95 @@
+use std::ffi::c_void;
+use std::path::Path;
+use windows::core::{w, HSTRING};
+use windows::Win32::Storage::FileSystem::{
+    GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, VS_FIXEDFILEINFO,
+};
+
+/// Returns the product version from the version resource of the executable
+/// at `path`, such as `131.0.6778.86`, or `None` if it has none.
+pub fn product_version(path: &Path) -> Option<String> {
+    let block = version_info(path)?;
+    unsafe {
+        let mut info: *mut c_void = std::ptr::null_mut();
+        let mut len = 0;
+        if !VerQueryValueW(
+            block.as_ptr() as *const c_void,
+            w!("\\"),
+            &mut info,
+            &mut len,
+        )
+        .as_bool()
+            || (len as usize) < std::mem::size_of::<VS_FIXEDFILEINFO>()
+        {
+            return None;
+        }
+        let info = &*(info as *const VS_FIXEDFILEINFO);
+        Some(format!(
+            "{}.{}.{}.{}",
+            info.dwProductVersionMS >> 16,
+            info.dwProductVersionMS & 0xffff,
+            info.dwProductVersionLS >> 16,
+            info.dwProductVersionLS & 0xffff
+        ))
+    }
+}
+
+/// Returns the file description from the version resource of the executable
+/// at `path`, which is usually the app's display name, such as `Visual Studio
+/// Code` for `Code.exe`. Uses the first language the resource lists.
+pub fn file_description(path: &Path) -> Option<String> {
+    let block = version_info(path)?;
+    unsafe {
+        let mut translations: *mut c_void = std::ptr::null_mut();
+        let mut len = 0;
+        if !VerQueryValueW(
+            block.as_ptr() as *const c_void,
+            w!("\\VarFileInfo\\Translation"),
+            &mut translations,
+            &mut len,
+        )
+        .as_bool()
+            || len < 4
+        {
+            return None;
+        }
+        let language = *(translations as *const u16);
+        let code_page = *(translations as *const u16).add(1);
+        let key = HSTRING::from(format!(
+            "\\StringFileInfo\\{:04x}{:04x}\\FileDescription",
+            language, code_page
+        ));
+
+        let mut description: *mut c_void = std::ptr::null_mut();
+        let mut len = 0;
+        if !VerQueryValueW(
+            block.as_ptr() as *const c_void,
+            &key,
+            &mut description,
+            &mut len,
+        )
+        .as_bool()
+            || len == 0
+        {
+            return None;
+        }
+        let description = std::slice::from_raw_parts(description as *const u16, len as usize);
+        let description = String::from_utf16_lossy(description);
+        let description = description.trim_end_matches('\0').trim();
+        (!description.is_empty()).then(|| description.to_string())
+    }
+}
+
+/// Reads the whole version resource of the executable at `path`.
+fn version_info(path: &Path) -> Option<Vec<u8>> {
+    let path = HSTRING::from(path.as_os_str());
+    unsafe {
+        let size = GetFileVersionInfoSizeW(&path, None);
+        if size == 0 {
+            return None;
+        }
+        let mut block = vec![0u8; size as usize];
+        GetFileVersionInfoW(&path, 0, size, block.as_mut_ptr() as *mut c_void).ok()?;
+        Some(block)
+    }
+}
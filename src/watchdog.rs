@@ -0,0 +1,90 @@
+use crate::log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Progress shared between a poll worker and the watchdog supervising it.
+pub struct Liveness {
+    generation: AtomicU64,
+    /// What progress is measured from, on a clock that system time changes
+    /// cannot move.
+    started: Instant,
+    /// Milliseconds from `started` to the last progress.
+    last_progress: AtomicU64,
+}
+
+impl Liveness {
+    pub fn new() -> Self {
+        Liveness {
+            generation: AtomicU64::new(0),
+            started: Instant::now(),
+            last_progress: AtomicU64::new(0),
+        }
+    }
+
+    /// Records that the worker of `generation` is still making progress.
+    /// Returns false once that worker has been replaced and should exit.
+    pub fn beat(&self, generation: u64) -> bool {
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return false;
+        }
+        self.last_progress.store(self.elapsed(), Ordering::SeqCst);
+        true
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn stalled_for(&self) -> u64 {
+        self.elapsed()
+            .saturating_sub(self.last_progress.load(Ordering::SeqCst))
+    }
+}
+
+/// Runs `worker` on a separate thread and restarts it whenever it panics or
+/// makes no progress for `timeout` milliseconds. A stalled worker cannot be
+/// killed, so it is left behind and exits on its own if it ever wakes up.
+/// Returns once a worker finishes normally.
+pub fn supervise<F>(timeout: u32, worker: F)
+where
+    F: Fn(&Liveness, u64) + Send + Sync + 'static,
+{
+    let worker = Arc::new(worker);
+    let liveness = Arc::new(Liveness::new());
+    let mut handle = spawn(&worker, &liveness);
+
+    loop {
+        thread::sleep(Duration::from_millis((timeout / 4).max(100).into()));
+        if handle.is_finished() {
+            match handle.join() {
+                Ok(()) => return,
+                Err(_) => warn!("Poll worker panicked. Restarting it"),
+            }
+        } else {
+            let stalled_for = liveness.stalled_for();
+            if stalled_for <= timeout.into() {
+                continue;
+            }
+            warn!(
+                "Poll worker made no progress for {}ms. Restarting it",
+                stalled_for
+            );
+        }
+        handle = spawn(&worker, &liveness);
+    }
+}
+
+fn spawn<F>(worker: &Arc<F>, liveness: &Arc<Liveness>) -> JoinHandle<()>
+where
+    F: Fn(&Liveness, u64) + Send + Sync + 'static,
+{
+    let generation = liveness.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    liveness
+        .last_progress
+        .store(liveness.elapsed(), Ordering::SeqCst);
+    let worker = Arc::clone(worker);
+    let liveness = Arc::clone(liveness);
+    thread::spawn(move || worker(&liveness, generation))
+}
@@ -0,0 +1,73 @@
+use crate::jittered;
+use crate::log::warn;
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const ATTEMPTS: u32 = 5;
+
+/// POSTs finished window events to a URL as JSON.
+pub struct Webhook {
+    sender: Sender<Vec<u8>>,
+}
+
+impl Webhook {
+    /// Starts a thread that POSTs queued events to `url` one at a time,
+    /// retrying failures with a jittered exponential backoff.
+    pub fn new(url: &str, jitter: f64) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let url = url.to_string();
+        thread::spawn(move || {
+            let client = match Client::builder().timeout(TIMEOUT).build() {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Failed to create the webhook client: {}", e);
+                    return;
+                }
+            };
+            for body in receiver {
+                post(&client, &url, body, jitter);
+            }
+        });
+        Webhook { sender }
+    }
+
+    /// Queues `event` for sending without blocking the poll loop.
+    pub fn send(&self, event: &aw_client_rust::Event) {
+        match serde_json::to_vec(event) {
+            Ok(body) => {
+                // The thread only stops if the client could not be created,
+                // which has already been logged.
+                let _ = self.sender.send(body);
+            }
+            Err(e) => warn!("Failed to serialize event for the webhook: {}", e),
+        }
+    }
+}
+
+fn post(client: &Client, url: &str, body: Vec<u8>, jitter: f64) {
+    let mut delay = Duration::from_secs(1);
+    for attempt in 1..=ATTEMPTS {
+        let result = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return,
+            Err(e) if attempt < ATTEMPTS => {
+                warn!("Failed to POST event to the webhook: {}. Retrying...", e);
+                thread::sleep(jittered(delay, jitter));
+                delay *= 2;
+            }
+            Err(e) => warn!(
+                "Failed to POST event to the webhook: {}. Giving up after {} attempts",
+                e, ATTEMPTS
+            ),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use clap::ValueEnum;
+use windows::Win32::Foundation::{HWND as RawHwnd, LPARAM, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    SendMessageTimeoutW, SMTO_ABORTIFHUNG, SMTO_BLOCK, WM_GETTEXT, WM_GETTEXTLENGTH,
+};
+use winsafe::{prelude::*, HWND};
+
+/// How long a window gets to answer each message before it is treated as
+/// hung, so a stuck window can never stall the poll.
+const TIMEOUT_MS: u32 = 100;
+
+/// Where a window title can be read from. Each is tried in the order
+/// given until one returns a title.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum TitleSource {
+    /// The caption Windows keeps for the window
+    GetWindowText,
+    /// Asking the window itself with WM_GETTEXT
+    WmGettext,
+    /// The window's UI Automation name
+    Uia,
+    /// The window's MSAA (IAccessible) name
+    Msaa,
+}
+
+/// Returns whether the window answers a message in time. UI Automation
+/// and MSAA calls into a hung window could block, so they are skipped.
+#[cfg(feature = "uia")]
+pub fn responds(hwnd: &HWND) -> bool {
+    use windows::Win32::UI::WindowsAndMessaging::WM_NULL;
+    let ok = unsafe {
+        SendMessageTimeoutW(
+            RawHwnd(hwnd.ptr()),
+            WM_NULL,
+            WPARAM(0),
+            LPARAM(0),
+            SMTO_ABORTIFHUNG | SMTO_BLOCK,
+            TIMEOUT_MS,
+            None,
+        )
+    };
+    ok.0 != 0
+}
+
+/// Asks the window for its title with WM_GETTEXT.
+///
+/// GetWindowText only reads the caption Windows keeps for windows of other
+/// processes, which some Qt and Java apps leave empty while still answering
+/// WM_GETTEXT. Returns `None` if the window does not answer in time.
+pub fn get_text(hwnd: &HWND) -> Option<String> {
+    let hwnd = RawHwnd(hwnd.ptr());
+    let flags = SMTO_ABORTIFHUNG | SMTO_BLOCK;
+    unsafe {
+        let mut len = 0usize;
+        let ok = SendMessageTimeoutW(
+            hwnd,
+            WM_GETTEXTLENGTH,
+            WPARAM(0),
+            LPARAM(0),
+            flags,
+            TIMEOUT_MS,
+            Some(&mut len),
+        );
+        if ok.0 == 0 || len == 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u16; len + 1];
+        let mut copied = 0usize;
+        let ok = SendMessageTimeoutW(
+            hwnd,
+            WM_GETTEXT,
+            WPARAM(buffer.len()),
+            LPARAM(buffer.as_mut_ptr() as isize),
+            flags,
+            TIMEOUT_MS,
+            Some(&mut copied),
+        );
+        if ok.0 == 0 {
+            return None;
+        }
+        Some(String::from_utf16_lossy(&buffer[..copied.min(len)]))
+    }
+}
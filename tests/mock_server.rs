@@ -0,0 +1,228 @@
+//! Runs the watcher against a mock aw-server and checks the requests it
+//! makes. The windows come from `replay`, so the switches are the same on
+//! every run and no real window is looked at.
+#![cfg(windows)]
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+const BUCKET: &str = "test-bucket";
+
+/// A request as the mock server received it.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    body: Value,
+}
+
+type Requests = Arc<Mutex<Vec<Request>>>;
+
+/// Starts a server on a free port that answers like aw-server and records
+/// every request. Returns the port and the requests received so far.
+fn mock_server() -> (u16, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Requests::default();
+    let recorded = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let recorded = recorded.clone();
+            thread::spawn(move || serve(stream.unwrap(), &recorded));
+        }
+    });
+    (port, requests)
+}
+
+/// Answers the requests on one connection until the client closes it.
+fn serve(stream: TcpStream, requests: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let response = if path == "/api/0/info" {
+            serde_json::json!({
+                "hostname": "mock",
+                "version": "v0.13.0",
+                "testing": true,
+                "device_id": "mock",
+            })
+            .to_string()
+        } else {
+            body.to_string()
+        };
+        requests
+            .lock()
+            .unwrap()
+            .push(Request { method, path, body });
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+    }
+}
+
+/// Writes a replay file with one row per `(millis, app, title)`, the millis
+/// counted from an arbitrary start, and returns its path.
+fn replay_file(name: &str, rows: &[(i64, &str, &str)]) -> PathBuf {
+    let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "aw-watcher-window-rs-{}-{}.jsonl",
+        name,
+        std::process::id()
+    ));
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|&(millis, app, title)| {
+            serde_json::json!({
+                "timestamp": (start + Duration::milliseconds(millis)).to_rfc3339(),
+                "app": app,
+                "title": title,
+            })
+            .to_string()
+        })
+        .collect();
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    path
+}
+
+/// Runs the watcher on `replay` against the server on `port` until the
+/// replay is over.
+fn run_watcher(port: u16, replay: &PathBuf) {
+    // An empty LOCALAPPDATA keeps a real config file from being read.
+    let appdata = std::env::temp_dir().join(format!(
+        "aw-watcher-window-rs-appdata-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&appdata).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aw-watcher-window-rs"))
+        .env("LOCALAPPDATA", &appdata)
+        .args(["--port", &port.to_string()])
+        .args(["--bucket-name", BUCKET, "--poll-time", "100"])
+        .arg("replay")
+        .arg("--input")
+        .arg(replay)
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > std::time::Duration::from_secs(30) {
+            child.kill().unwrap();
+            panic!("the watcher did not stop after the replay");
+        }
+        thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(child.wait().unwrap().success());
+}
+
+fn timestamp(event: &Value) -> DateTime<Utc> {
+    event["timestamp"].as_str().unwrap().parse().unwrap()
+}
+
+fn duration(event: &Value) -> Duration {
+    Duration::milliseconds((event["duration"].as_f64().unwrap() * 1000.0).round() as i64)
+}
+
+fn window(event: &Value) -> (&str, &str) {
+    (
+        event["data"]["app"].as_str().unwrap(),
+        event["data"]["title"].as_str().unwrap(),
+    )
+}
+
+#[test]
+fn sends_a_heartbeat_for_each_window_switch() {
+    let (port, requests) = mock_server();
+    let replay = replay_file(
+        "switches",
+        &[
+            (0, "Code.exe", "main.rs - Visual Studio Code"),
+            (600, "firefox.exe", "Inbox - Mozilla Firefox"),
+            (1200, "Code.exe", "lib.rs - Visual Studio Code"),
+        ],
+    );
+    run_watcher(port, &replay);
+    std::fs::remove_file(&replay).unwrap();
+    let requests = requests.lock().unwrap();
+
+    assert_eq!(
+        (requests[0].method.as_str(), requests[0].path.as_str()),
+        ("GET", "/api/0/info")
+    );
+    assert_eq!(requests[1].method, "POST");
+    assert_eq!(requests[1].path, format!("/api/0/buckets/{}", BUCKET));
+    assert_eq!(requests[1].body["type"], "currentwindow");
+
+    let heartbeats: Vec<&Value> = requests[2..]
+        .iter()
+        .map(|request| {
+            assert_eq!(request.method, "POST");
+            let (path, query) = request.path.split_once('?').unwrap();
+            assert_eq!(path, format!("/api/0/buckets/{}/heartbeat", BUCKET));
+            // The default pulsetime is the poll time plus one second.
+            let pulsetime = query.strip_prefix("pulsetime=").unwrap();
+            assert_eq!(pulsetime.parse::<f64>().unwrap(), 1.1);
+            &request.body
+        })
+        .collect();
+
+    // Consecutive heartbeats for the same window merge into one event, so
+    // only the switches matter.
+    let mut windows: Vec<(&str, &str)> = heartbeats.iter().map(|event| window(event)).collect();
+    windows.dedup();
+    assert_eq!(
+        windows,
+        [
+            ("Code.exe", "main.rs - Visual Studio Code"),
+            ("firefox.exe", "Inbox - Mozilla Firefox"),
+            ("Code.exe", "lib.rs - Visual Studio Code"),
+        ]
+    );
+
+    for pair in heartbeats.windows(2) {
+        let (previous, next) = (pair[0], pair[1]);
+        assert!(timestamp(previous) <= timestamp(next), "{:?}", pair);
+        if window(previous) != window(next) {
+            // The old window's event ends before the new one starts, so
+            // the server never sees them overlap.
+            assert!(
+                timestamp(previous) + duration(previous) < timestamp(next),
+                "{:?}",
+                pair
+            );
+        }
+    }
+}